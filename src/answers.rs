@@ -0,0 +1,78 @@
+use crate::output::Answer;
+use anyhow::Result;
+use anyhow::anyhow;
+
+/// The outcome of checking an answer against the manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Pass,
+    Fail { expected: String },
+    Missing,
+}
+
+/// Expected answers keyed by subcommand, read from a TOML file such as:
+///
+/// ```toml
+/// 1_1 = 1292
+/// 4_2 = 4590
+/// ```
+pub struct AnswerManifest {
+    expected: std::collections::HashMap<String, String>,
+}
+
+impl AnswerManifest {
+    pub fn parse(text: &str) -> Result<Self> {
+        let table: toml::value::Table = toml::from_str(text)?;
+
+        let expected = table.into_iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    toml::Value::Integer(number) => number.to_string(),
+                    toml::Value::String(text) => text,
+                    other => return Err(anyhow!("Expected answer of '{}' must be an integer or a string, not {}", key, other.type_str())),
+                };
+
+                Ok((key, value))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { expected })
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        use anyhow::Context;
+
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read answers file '{}'", path))?;
+
+        Self::parse(&text).with_context(|| format!("Failed to parse answers file '{}'", path))
+    }
+
+    pub fn check(&self, subcommand: &str, answer: &Answer) -> Verdict {
+        match self.expected.get(subcommand) {
+            None => Verdict::Missing,
+            Some(expected) if expected.trim() == answer.to_string().trim() => Verdict::Pass,
+            Some(expected) => Verdict::Fail { expected: expected.clone() },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::answers::AnswerManifest;
+    use crate::answers::Verdict;
+    use crate::output::Answer;
+
+    const MANIFEST: &str = "\
+        1_1 = 7\n\
+        \"4_2\" = \"1924\"\n";
+
+    #[test]
+    fn it_checks_answers_against_manifest() {
+        let manifest = AnswerManifest::parse(MANIFEST).unwrap();
+
+        assert_eq!(manifest.check("1_1", &Answer::from(7usize)), Verdict::Pass);
+        assert_eq!(manifest.check("4_2", &Answer::from(1924u32)), Verdict::Pass);
+        assert_eq!(manifest.check("1_1", &Answer::from(8usize)), Verdict::Fail { expected: "7".to_string() });
+        assert_eq!(manifest.check("6_1", &Answer::from(5934u64)), Verdict::Missing);
+    }
+}
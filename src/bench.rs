@@ -0,0 +1,196 @@
+use crate::command_line::read_subcommand_input;
+use crate::command_line::ChallengeSolutionArgs;
+use crate::core::input::InputLines;
+use anyhow::Result;
+use anyhow::anyhow;
+
+/// Summary of repeated measurements, in microseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Statistics {
+    pub min: f64,
+    pub median: f64,
+    pub mean: f64,
+    pub stddev: f64,
+}
+
+impl Statistics {
+    pub fn from_samples(samples: &[std::time::Duration]) -> Self {
+        if samples.is_empty() {
+            return Self { min: 0.0, median: 0.0, mean: 0.0, stddev: 0.0 };
+        }
+
+        let mut micros: Vec<f64> = samples.iter().map(|sample| sample.as_secs_f64() * 1_000_000.0).collect();
+        micros.sort_by(|a, b| a.total_cmp(b));
+
+        let count = micros.len() as f64;
+        let mean = micros.iter().sum::<f64>() / count;
+        let variance = micros.iter().map(|sample| (sample - mean).powi(2)).sum::<f64>() / count;
+        let middle = micros.len() / 2;
+        let median = if micros.len().is_multiple_of(2) { (micros[middle - 1] + micros[middle]) / 2.0 } else { micros[middle] };
+
+        Self { min: micros[0], median, mean, stddev: variance.sqrt() }
+    }
+}
+
+/// How many times a solution is run by [`benchmark`].
+#[derive(Debug, Clone, Copy)]
+pub struct BenchmarkOptions {
+    pub runs: usize,
+    // Runs done before the measured ones, which are not taken into account
+    pub warmup: usize,
+}
+
+/// Timings of a benchmarked solution. Loading the input is done once, while parsing and
+/// solving are measured separately on every run.
+pub struct BenchmarkResult {
+    pub subcommand: &'static str,
+    // Name in tables and baselines, the subcommand followed by the arguments of its variant if any
+    pub label: String,
+    pub load: std::time::Duration,
+    pub parse: Statistics,
+    pub solve: Statistics,
+}
+
+/// Loads the input of `solution` into memory and then parses and solves it repeatedly.
+pub fn benchmark(solution: &mut dyn ChallengeSolutionArgs, matches: &clap::ArgMatches, options: BenchmarkOptions) -> Result<BenchmarkResult> {
+    let subcommand = solution.get_subcommand();
+
+    let start = std::time::Instant::now();
+    let lines = read_subcommand_input(subcommand, matches)?.collect::<std::io::Result<Vec<String>>>()?;
+    let load = start.elapsed();
+
+    let mut parse_samples = Vec::with_capacity(options.runs);
+    let mut solve_samples = Vec::with_capacity(options.runs);
+
+    for run in 0..options.warmup + options.runs {
+        let input: InputLines = Box::new(lines.clone().into_iter().map(Ok));
+
+        let start = std::time::Instant::now();
+        let prepared = solution.prepare(input, matches)?;
+        let parse = start.elapsed();
+
+        let start = std::time::Instant::now();
+        std::hint::black_box(prepared()?);
+        let solve = start.elapsed();
+
+        if run >= options.warmup {
+            parse_samples.push(parse);
+            solve_samples.push(solve);
+        }
+    }
+
+    Ok(BenchmarkResult {
+        subcommand,
+        label: subcommand.to_string(),
+        load,
+        parse: Statistics::from_samples(&parse_samples),
+        solve: Statistics::from_samples(&solve_samples),
+    })
+}
+
+/// Median parse and solve times from an earlier benchmark, stored in a file with lines of
+/// 'label<TAB>parse_us<TAB>solve_us'.
+#[derive(Default)]
+pub struct Baseline {
+    medians: std::collections::HashMap<String, (f64, f64)>,
+}
+
+impl Baseline {
+    pub fn parse(text: &str) -> Result<Self> {
+        let medians = text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let fields: Vec<&str> = line.split('\t').collect();
+                match fields[..] {
+                    [label, parse, solve] => Ok((label.to_string(), (parse.parse::<f64>()?, solve.parse::<f64>()?))),
+                    _ => Err(anyhow!("Malformed baseline line '{}'", line)),
+                }
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { medians })
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        use anyhow::Context;
+
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read baseline '{}'", path))?;
+
+        Self::parse(&text).with_context(|| format!("Failed to parse baseline '{}'", path))
+    }
+
+    pub fn from_results(results: &[BenchmarkResult]) -> Self {
+        let medians = results.iter()
+            .map(|result| (result.label.clone(), (result.parse.median, result.solve.median)))
+            .collect();
+
+        Self { medians }
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        use anyhow::Context;
+
+        let mut entries: Vec<_> = self.medians.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+
+        let text: String = entries.into_iter()
+            .map(|(label, (parse, solve))| format!("{}\t{:.3}\t{:.3}\n", label, parse, solve))
+            .collect();
+
+        std::fs::write(path, text).with_context(|| format!("Failed to write baseline '{}'", path))
+    }
+
+    /// Returns the ratio of the median solve time of `result` to the one in the baseline.
+    pub fn solve_ratio(&self, result: &BenchmarkResult) -> Option<f64> {
+        self.medians.get(&result.label)
+            .filter(|(_, solve)| *solve > 0.0)
+            .map(|(_, solve)| result.solve.median / solve)
+    }
+}
+
+/// Writes the results as a table, with the change of the median solve time if a baseline is given.
+pub fn write_table(results: &[BenchmarkResult], baseline: Option<&Baseline>, out: &mut impl std::io::Write) -> std::io::Result<()> {
+    let width = results.iter().map(|result| result.label.len()).max().unwrap_or(0).max(10);
+
+    writeln!(out, "{:<width$}  {:>10}  {:>12}  {:>12}  {:>12}  {:>12}  {:>12}  {:>10}",
+        "Solution", "Load", "Parse (med)", "Solve (min)", "Solve (med)", "Solve (mean)", "Solve (sd)", "Baseline", width = width)?;
+
+    for result in results {
+        let change = match baseline.and_then(|baseline| baseline.solve_ratio(result)) {
+            Some(ratio) => format!("{:+.1}%", (ratio - 1.0) * 100.0),
+            None => "-".to_string(),
+        };
+
+        writeln!(out, "{:<width$}  {:>8.1}us  {:>10.1}us  {:>10.1}us  {:>10.1}us  {:>10.1}us  {:>10.1}us  {:>10}",
+            result.label, result.load.as_secs_f64() * 1_000_000.0, result.parse.median,
+            result.solve.min, result.solve.median, result.solve.mean, result.solve.stddev, change, width = width)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bench::Baseline;
+    use crate::bench::Statistics;
+    use std::time::Duration;
+
+    #[test]
+    fn it_computes_statistics_of_samples() {
+        let samples = [4, 1, 3, 2].map(Duration::from_micros);
+        let statistics = Statistics::from_samples(&samples);
+
+        assert_eq!(statistics.min, 1.0);
+        assert_eq!(statistics.median, 2.5);
+        assert_eq!(statistics.mean, 2.5);
+        assert!((statistics.stddev - 1.25f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn it_parses_baseline() {
+        let baseline = Baseline::parse("5_1\t10.0\t200.5\n6_2\t1.0\t2.0\n").unwrap();
+
+        assert_eq!(baseline.medians.get("5_1"), Some(&(10.0, 200.5)));
+        assert!(Baseline::parse("5_1\t10.0").is_err());
+    }
+}
@@ -1,52 +1,138 @@
-use crate::core::file::read_lines;
+use crate::core::input::day_file_name;
+use crate::core::input::InputLines;
+use crate::core::input::InputSource;
+use crate::error::UsageError;
+use crate::output::Answer;
 use anyhow::Result;
 use anyhow::anyhow;
 
+/// A solution with its input already parsed. It can be called repeatedly, e.g. by benchmarks.
+pub type PreparedSolution = Box<dyn Fn() -> Result<Answer>>;
+
+/// A single puzzle solution that can be invoked as a subcommand of the command line.
 pub trait ChallengeSolutionArgs {
+    /// Parses the lines of the input, returning the solution ready to be solved.
+    fn prepare(&mut self, input: InputLines, matches: &clap::ArgMatches) -> Result<PreparedSolution>;
     // Returns the name for the subcommand, by which the args can be indentified back
-    fn run(&mut self, matches: &clap::ArgMatches) -> Result<String>;
     fn get_subcommand(&self) -> &'static str;
+
+    /// Adds the arguments specific to this solution to its subcommand.
+    fn configure<'a>(&self, subcommand: clap::App<'a>) -> clap::App<'a> {
+        subcommand
+    }
+
+    /// Whether the solution is run by the commands running many solutions at once, such as `all`
+    /// and `bench`. Solutions that wait for the user opt out.
+    fn runs_in_batch(&self) -> bool {
+        true
+    }
+
+    /// Reads the input selected by `matches` and returns the answer.
+    fn run(&mut self, matches: &clap::ArgMatches) -> Result<Answer> {
+        let input = read_subcommand_input(self.get_subcommand(), matches)?;
+        let solution = self.prepare(input, matches)?;
+
+        solution()
+    }
 }
 
 impl dyn ChallengeSolutionArgs {
     fn add_subcommand<'a>(&self, app: clap::App<'a>) -> (&'static str, clap::App<'a>) {
-        (self.get_subcommand(), add_input(app, self.get_subcommand()))
+        let subcommand = self.configure(input_subcommand(self.get_subcommand()));
+
+        (self.get_subcommand(), app.subcommand(subcommand))
     }
 
 }
 
-pub fn read_input_from_matches(args: &impl ChallengeSolutionArgs, matches: &clap::ArgMatches) -> anyhow::Result<std::io::Lines<std::io::BufReader<std::fs::File>>> {
+// Directory searched for 'day_N.txt' when no input was given
+const DEFAULT_INPUT_DIRECTORY: &str = "inputs";
+
+/// Opens the input passed to the subcommand of `args` and returns an iterator over its lines.
+pub fn read_input_from_matches(args: &impl ChallengeSolutionArgs, matches: &clap::ArgMatches) -> anyhow::Result<InputLines> {
+    read_subcommand_input(args.get_subcommand(), matches)
+}
+
+/// Opens the input passed to `subcommand` and returns an iterator over its lines.
+pub fn read_subcommand_input(subcommand: &'static str, matches: &clap::ArgMatches) -> anyhow::Result<InputLines> {
     use anyhow::Context;
 
-    let submatches = expect_submatches(matches, args.get_subcommand());
-    let input_path = get_input_path(submatches)?;
+    let submatches = expect_submatches(matches, subcommand);
+    let source = get_input_source(submatches, subcommand);
+    let description = source.to_string();
 
-    read_lines(input_path).with_context(|| "Failed to read file")
+    source.lines().with_context(|| format!("Failed to read input '{}'", description))
 }
 
+/// Maps subcommand names back to the solutions that registered them.
 pub struct ClapSubcommandResolver {
-    subcommands: std::collections::HashMap<&'static str, Box<dyn ChallengeSolutionArgs>>
+    subcommands: std::collections::HashMap<&'static str, Box<dyn ChallengeSolutionArgs>>,
+    // Subcommands in the order they were registered in
+    order: Vec<&'static str>,
+}
+
+impl Default for ClapSubcommandResolver {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ClapSubcommandResolver {
     pub fn new() -> Self {
         Self {
-            subcommands: std::collections::HashMap::new()
+            subcommands: std::collections::HashMap::new(),
+            order: Vec::new(),
         }
     }
 
     fn add_subcommand(&mut self, subcommand: &'static str, solution_args: Box<dyn ChallengeSolutionArgs>) {
-        self.subcommands.insert(subcommand, solution_args);
+        if self.subcommands.insert(subcommand, solution_args).is_none() {
+            self.order.push(subcommand);
+        }
+    }
+
+    /// Returns the registered subcommands in the order they were registered in.
+    pub fn subcommands(&self) -> &[&'static str] {
+        &self.order
+    }
+
+    /// Returns the registered subcommands that [`ChallengeSolutionArgs::runs_in_batch`].
+    pub fn batch_subcommands(&self) -> Vec<&'static str> {
+        self.order.iter()
+            .filter(|subcommand| self.subcommands[*subcommand].runs_in_batch())
+            .copied()
+            .collect()
+    }
+
+    /// Returns the solution registered under `subcommand`.
+    pub fn get_mut(&mut self, subcommand: &str) -> Result<&mut Box<dyn ChallengeSolutionArgs>> {
+        self.subcommands.get_mut(subcommand).ok_or_else(|| unknown_subcommand(subcommand))
+    }
+
+    /// Parses `args` as if `subcommand` was invoked with them from the command line, so the
+    /// returned matches can be passed to [`ChallengeSolutionArgs::run`].
+    pub fn matches_for(&self, subcommand: &str, args: &[String]) -> Result<clap::ArgMatches> {
+        let solution_args = self.subcommands.get(subcommand).ok_or_else(|| unknown_subcommand(subcommand))?;
+        let (_, app) = solution_args.add_subcommand(clap::App::new("aoc2021"));
+
+        let argv = ["aoc2021", subcommand].into_iter().map(String::from).chain(args.iter().cloned());
+        Ok(app.try_get_matches_from(argv)?)
     }
 
+    /// Returns the solution for the subcommand that was invoked in `matches`.
     pub fn resolve(&mut self, matches: &clap::ArgMatches) -> Result<&mut Box<dyn ChallengeSolutionArgs>>{
         match matches.subcommand() {
-            Some((subcommand, _)) => self.subcommands.get_mut(subcommand).ok_or(anyhow!("'{}' is not known subcommand.", subcommand)),
-            None => Err(anyhow!("No command was specified."))
+            Some((subcommand, _)) => self.get_mut(subcommand),
+            None => Err(UsageError("No command was specified.".to_string()).into())
         }
     }
 }
 
+fn unknown_subcommand(subcommand: &str) -> anyhow::Error {
+    UsageError(format!("'{}' is not known subcommand.", subcommand)).into()
+}
+
+/// Extension of [`clap::App`] for registering puzzle solutions as subcommands.
 pub trait ClapAppExt {
     fn aoc_solution(self, solution_args: Box<dyn ChallengeSolutionArgs>, resolver: &mut ClapSubcommandResolver) -> Self;
 }
@@ -60,17 +146,116 @@ impl ClapAppExt for clap::App<'static> {
 }
 
 
+/// Adds `subcommand` to `app` with the arguments selecting its input.
 pub fn add_input<'a>(app: clap::App<'a>, subcommand: &'static str) -> clap::App<'a> {
-    app.subcommand(clap::App::new(subcommand)
-        .arg(clap::Arg::new("input").short('i').takes_value(true).required(true))
-    )
+    app.subcommand(input_subcommand(subcommand))
 }
 
+/// Creates `subcommand` with the arguments selecting its input.
+pub fn input_subcommand<'a>(subcommand: &'static str) -> clap::App<'a> {
+    clap::App::new(subcommand)
+        .arg(clap::Arg::new("input").short('i').long("input").takes_value(true).value_name("PATH")
+            .help("Input file, directory containing 'day_N.txt' or '-' for stdin [default: inputs/day_N.txt]"))
+        .arg(clap::Arg::new("input-str").long("input-str").takes_value(true).value_name("TEXT")
+            .conflicts_with("input")
+            .help("Puzzle input given inline"))
+}
+
+/// Parses the value of the argument `name` of the invoked `subcommand`, reporting a usage error
+/// when it can't be parsed. Returns `None` if the argument wasn't given and has no default value.
+pub fn parse_optional_arg<T>(matches: &clap::ArgMatches, subcommand: &'static str, name: &str) -> Result<Option<T>>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    let submatches = expect_submatches(matches, subcommand);
+
+    submatches.value_of(name)
+        .map(|value| value.parse::<T>().map_err(|err| UsageError(format!("'{}' is not a valid value for --{}: {}", value, name, err)).into()))
+        .transpose()
+}
+
+/// Same as [`parse_optional_arg`], for arguments that are required or have a default value.
+pub fn parse_arg<T>(matches: &clap::ArgMatches, subcommand: &'static str, name: &str) -> Result<T>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    parse_optional_arg(matches, subcommand, name)?
+        .ok_or_else(|| UsageError(format!("Missing value for --{}", name)).into())
+}
+
+/// Returns the day number of a subcommand named like '4_2'.
+pub fn day_of_subcommand(subcommand: &str) -> &str {
+    subcommand.split('_').next().unwrap_or(subcommand)
+}
+
+/// Returns the input selected by the arguments added in [`add_input`].
+pub fn get_input_source(matches: &clap::ArgMatches, subcommand: &str) -> InputSource {
+    let day = day_of_subcommand(subcommand);
+
+    if let Some(text) = matches.value_of("input-str") {
+        InputSource::Inline(text.to_string())
+    } else if let Some(path) = matches.value_of("input") {
+        InputSource::from_path(path, day)
+    } else {
+        InputSource::File(std::path::Path::new(DEFAULT_INPUT_DIRECTORY).join(day_file_name(day)))
+    }
+}
+
+/// Returns how the input of the invoked `subcommand` is shown in reports.
+pub fn get_input_description(matches: &clap::ArgMatches, subcommand: &'static str) -> String {
+    let submatches = expect_submatches(matches, subcommand);
+
+    get_input_source(submatches, subcommand).to_string()
+}
+
+/// Parses a range of days such as '1..6' (inclusive on both ends), '3..', '..4' or a single day.
+pub fn parse_day_range(text: &str) -> Result<std::ops::RangeInclusive<u32>> {
+    let parse_day = |day: &str, default: u32| -> Result<u32> {
+        match day.trim() {
+            "" => Ok(default),
+            day => day.parse::<u32>().map_err(|_| UsageError(format!("'{}' is not a valid day", day)).into()),
+        }
+    };
+
+    let (start, end) = match text.split_once("..") {
+        Some((start, end)) => (parse_day(start, 1)?, parse_day(end.trim_start_matches('='), u32::MAX)?),
+        None => {
+            let day = parse_day(text, 1)?;
+            (day, day)
+        }
+    };
+
+    if start > end {
+        return Err(UsageError(format!("Range '{}' is empty", text)).into());
+    }
+
+    Ok(start..=end)
+}
+
+/// Returns the value of the `-i <input>` argument.
 pub fn get_input_path(matches: &clap::ArgMatches) -> Result<&str> {
     matches.value_of("input").ok_or(anyhow!("No input file was given"))
 }
 
+/// Returns the matches of `subcommand`, panicking if it wasn't the one invoked.
 pub fn expect_submatches<'a>(matches: &'a clap::ArgMatches, subcommand: &'static str) -> &'a clap::ArgMatches{
     matches.subcommand_matches(subcommand)
-        .expect(format!("Subcommand {} was not invoked", subcommand).as_str())
+        .unwrap_or_else(|| panic!("Subcommand {} was not invoked", subcommand))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::command_line::parse_day_range;
+
+    #[test]
+    fn it_parses_day_ranges() {
+        assert_eq!(parse_day_range("1..6").unwrap(), 1..=6);
+        assert_eq!(parse_day_range("2..=4").unwrap(), 2..=4);
+        assert_eq!(parse_day_range("3..").unwrap(), 3..=u32::MAX);
+        assert_eq!(parse_day_range("5").unwrap(), 5..=5);
+        assert!(parse_day_range("6..1").is_err());
+        assert!(parse_day_range("a..b").is_err());
+    }
 }
@@ -1,6 +1,7 @@
+/// Helpers for reading puzzle inputs from the filesystem.
 pub mod file {
-    
 
+    /// Opens the file at `path` and returns a buffered iterator over its lines.
     pub fn read_lines(path: &str) -> std::io::Result<std::io::Lines<std::io::BufReader<std::fs::File>>> {
         use std::io::BufRead;
 
@@ -12,4 +13,142 @@ pub mod file {
         Ok(reader.lines())
     }
 
-}
\ No newline at end of file
+}
+
+/// Sources from which a puzzle input can be read.
+pub mod input {
+    use std::io::BufRead;
+
+    /// Lines of a puzzle input, regardless of where it was read from.
+    pub type InputLines = Box<dyn Iterator<Item = std::io::Result<String>>>;
+
+    /// Name of the file with the input of the given day inside of an input directory.
+    pub fn day_file_name(day: &str) -> String {
+        format!("day_{}.txt", day)
+    }
+
+    /// Where the input of a puzzle comes from.
+    pub enum InputSource {
+        Stdin,
+        File(std::path::PathBuf),
+        Inline(String),
+    }
+
+    impl InputSource {
+        /// Resolves a path given by the user. `-` stands for stdin and a directory is searched
+        /// for the input file of the given day.
+        pub fn from_path(path: &str, day: &str) -> Self {
+            if path == "-" {
+                return InputSource::Stdin;
+            }
+
+            let path = std::path::PathBuf::from(path);
+            if path.is_dir() {
+                InputSource::File(path.join(day_file_name(day)))
+            } else {
+                InputSource::File(path)
+            }
+        }
+
+        pub fn lines(self) -> std::io::Result<InputLines> {
+            match self {
+                InputSource::Stdin => Ok(Box::new(std::io::stdin().lock().lines())),
+                InputSource::File(path) => {
+                    let file = std::fs::File::open(path)?;
+                    Ok(Box::new(std::io::BufReader::new(file).lines()))
+                },
+                InputSource::Inline(text) => {
+                    let lines: Vec<String> = text.lines().map(String::from).collect();
+                    Ok(Box::new(lines.into_iter().map(Ok)))
+                },
+            }
+        }
+    }
+
+    impl std::fmt::Display for InputSource {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                InputSource::Stdin => write!(f, "-"),
+                InputSource::File(path) => write!(f, "{}", path.display()),
+                InputSource::Inline(_) => write!(f, "<inline>"),
+            }
+        }
+    }
+}
+
+/// Encoders of simple uncompressed image formats.
+pub mod image {
+    use std::io::Write;
+
+    /// Writes a binary PGM (P5) image from grayscale pixels in rows from the top.
+    pub fn write_pgm(out: &mut impl Write, width: usize, height: usize, pixels: &[u8]) -> std::io::Result<()> {
+        assert_eq!(pixels.len(), width * height, "Pixels don't fill the image");
+
+        write!(out, "P5\n{} {}\n255\n", width, height)?;
+        out.write_all(pixels)
+    }
+
+    /// Writes a binary PPM (P6) image from RGB pixels in rows from the top.
+    pub fn write_ppm(out: &mut impl Write, width: usize, height: usize, pixels: &[[u8; 3]]) -> std::io::Result<()> {
+        assert_eq!(pixels.len(), width * height, "Pixels don't fill the image");
+
+        write!(out, "P6\n{} {}\n255\n", width, height)?;
+        out.write_all(&pixels.concat())
+    }
+
+    /// Maps `value` from 0.0 to 1.0 to a color going from black through blue and red to yellow.
+    pub fn heatmap_color(value: f64) -> [u8; 3] {
+        const STOPS: [[f64; 3]; 4] = [[0.0, 0.0, 0.0], [0.0, 0.0, 255.0], [255.0, 0.0, 0.0], [255.0, 255.0, 0.0]];
+
+        let scaled = value.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+        let index = (scaled as usize).min(STOPS.len() - 2);
+        let fraction = scaled - index as f64;
+
+        let (from, to) = (STOPS[index], STOPS[index + 1]);
+        [0, 1, 2].map(|channel| (from[channel] + (to[channel] - from[channel]) * fraction).round() as u8)
+    }
+}
+
+/// A small seeded pseudorandom generator, so that random runs can be repeated.
+pub mod random {
+    /// The SplitMix64 generator. It is fast and good enough for simulations, but not for
+    /// anything needing unpredictable numbers.
+    #[derive(Debug, Clone)]
+    pub struct SplitMix64 {
+        state: u64,
+    }
+
+    impl SplitMix64 {
+        pub fn new(seed: u64) -> Self {
+            Self { state: seed }
+        }
+
+        pub fn next_u64(&mut self) -> u64 {
+            self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = self.state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^ (z >> 31)
+        }
+
+        /// Returns a number from 0 up to, but excluding, `bound`, which must not be 0.
+        pub fn below(&mut self, bound: u64) -> u64 {
+            // Values above the last multiple of `bound` are drawn again, so that none is favored
+            let zone = u64::MAX - u64::MAX % bound;
+            loop {
+                let value = self.next_u64();
+                if value < zone {
+                    return value % bound;
+                }
+            }
+        }
+
+        /// Puts the items in a random order with the Fisher-Yates shuffle.
+        pub fn shuffle<T>(&mut self, items: &mut [T]) {
+            for i in (1..items.len()).rev() {
+                let j = self.below(i as u64 + 1) as usize;
+                items.swap(i, j);
+            }
+        }
+    }
+}
@@ -2,10 +2,12 @@ use std::collections::VecDeque;
 use anyhow::Result;
 
 pub mod cli {
-    use crate::command_line::read_input_from_matches;
     use crate::command_line::ChallengeSolutionArgs;
+    use crate::command_line::PreparedSolution;
+    use crate::core::input::InputLines;
     use crate::day_1::run_sonar_sliding_window;
     use crate::day_1::run_sonar_sweep_depth;
+    use crate::output::Answer;
 
     use anyhow::{ Context, Result };
 
@@ -13,7 +15,7 @@ pub mod cli {
     const SONAR_SLIDING_WINDOW_SUBCOMMAND: &str = "1_2";
 
     fn parse_value(read_result: std::io::Result<String>) -> Result<isize> {
-        let line = read_result.with_context(|| format!("Error while reading a line of file."))?;
+        let line = read_result.context("Error while reading a line of file.")?;
 
         line.parse::<isize>().with_context(|| format!("Can't parse line '{}' to isize.", line))
     }
@@ -25,10 +27,10 @@ pub mod cli {
             SONAR_SWEEP_DEPTH_SUBCOMMAND
         }
 
-        fn run(&mut self, matches: &clap::ArgMatches) -> Result<String> { 
-            let input = read_input_from_matches(self, matches)?.map(parse_value);
+        fn prepare(&mut self, input: InputLines, _matches: &clap::ArgMatches) -> Result<PreparedSolution> { 
+            let values = input.map(parse_value).collect::<Result<Vec<_>>>()?;
 
-            run_sonar_sweep_depth(input)
+            Ok(Box::new(move || run_sonar_sweep_depth(values.iter().copied().map(Ok)).map(Answer::from)))
         }
     }
 
@@ -39,15 +41,16 @@ pub mod cli {
             SONAR_SLIDING_WINDOW_SUBCOMMAND
         }
 
-        fn run(&mut self, matches: &clap::ArgMatches) -> Result<String> { 
-            let input = read_input_from_matches(self, matches)?.map(parse_value);
+        fn prepare(&mut self, input: InputLines, _matches: &clap::ArgMatches) -> Result<PreparedSolution> { 
+            let values = input.map(parse_value).collect::<Result<Vec<_>>>()?;
 
-            run_sonar_sliding_window(input)
+            Ok(Box::new(move || run_sonar_sliding_window(values.iter().copied().map(Ok)).map(Answer::from)))
         }
     }
 }
 
-pub fn run_sonar_sweep_depth<'a>(input: impl IntoIterator<Item = Result<isize>>) -> Result<String>{
+/// Counts how many depth measurements are larger than the previous one.
+pub fn run_sonar_sweep_depth(input: impl IntoIterator<Item = Result<isize>>) -> Result<isize> {
     let mut previous_value_option: Option<isize> = None;
     let mut increased_counter: isize = 0;
 
@@ -65,11 +68,12 @@ pub fn run_sonar_sweep_depth<'a>(input: impl IntoIterator<Item = Result<isize>>)
         previous_value_option = Some(value);
     }
 
-    Ok(format!("Answer: {}", increased_counter))
+    Ok(increased_counter)
 
 }
 
-pub fn run_sonar_sliding_window(input: impl IntoIterator<Item = Result<isize>>) -> Result<String> {
+/// Counts how many sums of three-measurement sliding windows are larger than the previous sum.
+pub fn run_sonar_sliding_window(input: impl IntoIterator<Item = Result<isize>>) -> Result<isize> {
     let mut previous_window_option: Option<isize> = None;
 
     let mut incomplete_windows: VecDeque<isize> = VecDeque::new();
@@ -96,6 +100,6 @@ pub fn run_sonar_sliding_window(input: impl IntoIterator<Item = Result<isize>>)
         }
     }
 
-    Ok(format!("Answer: {}", increased_counter))
+    Ok(increased_counter)
 
 }
\ No newline at end of file
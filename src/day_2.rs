@@ -1,11 +1,16 @@
-use anyhow::{ Result, anyhow };
+use crate::error::ParseError;
+use anyhow::Context;
+use anyhow::Result;
 
 
 pub mod cli {
-    use crate::command_line::read_input_from_matches;
-    use crate::day_2::run_aimed_dive;
-    use crate::day_2::run_dive;
+    use crate::day_2::follow_aimed_course;
+    use crate::day_2::follow_course;
+    use crate::day_2::read_course;
     use crate::command_line::ChallengeSolutionArgs;
+    use crate::command_line::PreparedSolution;
+    use crate::core::input::InputLines;
+    use crate::output::Answer;
     use anyhow::Result;
 
     const DIVE_SUBCOMMAND: &str = "2_1";
@@ -18,10 +23,10 @@ pub mod cli {
             DIVE_SUBCOMMAND
         }
 
-        fn run(&mut self, matches: &clap::ArgMatches) -> Result<String> { 
-            let input = read_input_from_matches(self, matches)?;
+        fn prepare(&mut self, input: InputLines, _matches: &clap::ArgMatches) -> Result<PreparedSolution> { 
+            let course = read_course(input)?;
         
-            run_dive(input).map(|r| r.to_string())
+            Ok(Box::new(move || Ok(Answer::from(follow_course(&course)))))
         }
     }
 
@@ -32,69 +37,93 @@ pub mod cli {
             AIMED_DIVE_SUBCOMMAND
         }
 
-        fn run(&mut self, matches: &clap::ArgMatches) -> Result<String> { 
-            let input = read_input_from_matches(self, matches)?;
+        fn prepare(&mut self, input: InputLines, _matches: &clap::ArgMatches) -> Result<PreparedSolution> { 
+            let course = read_course(input)?;
         
-            run_aimed_dive(input).map(|r| r.to_string())
+            Ok(Box::new(move || Ok(Answer::from(follow_aimed_course(&course)))))
         }
     }
 
 }
 
-fn parse_result<'a>(line: &'a str) -> Result<(&'a str, isize)> {
+/// A single step of the planned course.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Forward,
+    Down,
+    Up,
+}
+
+fn parse_result(line: &str) -> Result<(Command, isize)> {
     let mut splits = line.split(' ');
-    let command = splits.next().ok_or(anyhow!("The line is empty"))?;
-    let value = splits.next().ok_or(anyhow!("There is no second argument"))?.parse::<isize>()?;
+    let command = match splits.next().ok_or_else(|| ParseError("The line is empty".to_string()))? {
+        "forward" => Command::Forward,
+        "down" => Command::Down,
+        "up" => Command::Up,
+        command => return Err(ParseError(format!("Unknown command '{}'", command)).into()),
+    };
+    let value = splits.next().ok_or_else(|| ParseError(format!("There is no second argument in '{}'", line)))?
+        .parse::<isize>().with_context(|| format!("Can't parse the value in '{}'", line))?;
     
     Ok((command, value))
 }
 
-pub fn run_dive<S: AsRef<str>>(input: impl IntoIterator<Item = std::io::Result<S>>) -> Result<isize> {
+/// Parses the planned course into its commands and their values.
+pub fn read_course<S: AsRef<str>>(input: impl IntoIterator<Item = std::io::Result<S>>) -> Result<Vec<(Command, isize)>> {
+    input.into_iter()
+        .map(|result| parse_result(result?.as_ref()))
+        .collect()
+}
+
+/// Follows the course and returns the product of the final depth and horizontal position.
+pub fn follow_course(course: &[(Command, isize)]) -> isize {
     let mut depth = 0;
     let mut horizontal = 0;
 
-    for result in input {
-        let line = result?;
-
-        let (command, value) = parse_result(line.as_ref())?;
-
+    for &(command, value) in course {
         match command {
-            "forward" => horizontal += value,
-            "down" => depth += value,
-            "up" => depth -= value,
-            command => return Err(anyhow!("Unknown command '{}'", command))
+            Command::Forward => horizontal += value,
+            Command::Down => depth += value,
+            Command::Up => depth -= value,
         }
     }
 
-    Ok(depth * horizontal)
+    depth * horizontal
 }
 
-pub fn run_aimed_dive<S: AsRef<str>>(input: impl IntoIterator<Item = std::io::Result<S>>) -> Result<isize> {
+/// Same as [`follow_course`], but `up` and `down` change the aim of the submarine instead of the depth.
+pub fn follow_aimed_course(course: &[(Command, isize)]) -> isize {
     let mut depth = 0;
     let mut horizontal = 0;
     let mut aim = 0;
 
-    for result in input {
-        let line = result?;
-
-        let (command, value) = parse_result(line.as_ref())?;
-
+    for &(command, value) in course {
         match command {
-            "forward" => { 
+            Command::Forward => { 
                 horizontal += value;
                 depth += value * aim;
             },
-            "down" => aim += value,
-            "up" => aim -= value,
-            command => return Err(anyhow!("Unknown command '{}'", command))
+            Command::Down => aim += value,
+            Command::Up => aim -= value,
         }
     }
 
-    Ok(depth * horizontal)
+    depth * horizontal
+}
+
+/// Reads the course and returns the product of the final depth and horizontal position.
+pub fn run_dive<S: AsRef<str>>(input: impl IntoIterator<Item = std::io::Result<S>>) -> Result<isize> {
+    Ok(follow_course(&read_course(input)?))
+}
+
+/// Same as [`run_dive`], but `up` and `down` change the aim of the submarine instead of the depth.
+pub fn run_aimed_dive<S: AsRef<str>>(input: impl IntoIterator<Item = std::io::Result<S>>) -> Result<isize> {
+    Ok(follow_aimed_course(&read_course(input)?))
 }
 
 
 #[cfg(test)]
+#[allow(clippy::redundant_closure)]
 mod tests {
     use crate::day_2::run_aimed_dive;
     use crate::day_2::run_dive;
@@ -1,9 +1,8 @@
 pub mod cli {
-    use crate::day_3::run_life_support_rating;
-    use crate::day_3::read_input_to_vec;
-    use crate::command_line::read_input_from_matches;
-    use crate::day_3::run_binary_diagnostic;
+    use crate::day_3::read_diagnostic;
     use crate::command_line::ChallengeSolutionArgs;
+    use crate::command_line::PreparedSolution;
+    use crate::core::input::InputLines;
     use anyhow::Result;
 
     const BINARY_DIAGNOSTIC_SUBCOMMAND: &str = "3_1";
@@ -16,13 +15,14 @@ pub mod cli {
             BINARY_DIAGNOSTIC_SUBCOMMAND
         }
 
-        fn run(&mut self, matches: &clap::ArgMatches) -> Result<String> { 
-            let input = read_input_from_matches(self, matches)?;
-            let puzzle = read_input_to_vec(input)?;
+        fn prepare(&mut self, input: InputLines, _matches: &clap::ArgMatches) -> Result<PreparedSolution> { 
+            let puzzle = read_diagnostic(input)?;
             
-            let answer = run_binary_diagnostic(puzzle);
+            Ok(Box::new(move || {
+                let answer = puzzle.clone().power_consumption();
 
-            Ok(format!("Answer is: {}", answer))
+                Ok(answer)
+            }))
         }
     }
 
@@ -33,89 +33,245 @@ pub mod cli {
             LIFE_SUPPORT_RATING_SUBCOMMAND
         }
 
-        fn run(&mut self, matches: &clap::ArgMatches) -> Result<String> { 
-            let input = read_input_from_matches(self, matches)?;
-            let puzzle = read_input_to_vec(input)?;
+        fn prepare(&mut self, input: InputLines, _matches: &clap::ArgMatches) -> Result<PreparedSolution> { 
+            let puzzle = read_diagnostic(input)?;
             
-            let answer = run_life_support_rating(puzzle)?;
+            Ok(Box::new(move || {
+                let answer = puzzle.clone().life_support_rating()?;
 
-            Ok(format!("Answer is: {}", answer))
+                Ok(answer)
+            }))
         }
     }
 }
 
+use crate::error::ParseError;
+use crate::output::Answer;
+
+/// A single number of the diagnostic report.
 pub type PuzzleBinary = u32;
 
-pub struct PuzzleInput {
-    pub input: Vec<PuzzleBinary>,
+/// A number of the diagnostic report, stored in a type wide enough for its bits.
+pub trait DiagnosticBits: Clone {
+    // Product of two numbers, wide enough not to overflow
+    type Product: Into<Answer>;
+
+    fn zero(width: usize) -> Self;
+    fn bit(&self, pos: usize) -> bool;
+    fn set(&mut self, pos: usize);
+    fn multiply(&self, other: &Self) -> Self::Product;
+}
+
+impl DiagnosticBits for PuzzleBinary {
+    type Product = usize;
+
+    fn zero(_width: usize) -> Self {
+        0
+    }
+
+    fn bit(&self, pos: usize) -> bool {
+        self & (1 << pos) != 0
+    }
+
+    fn set(&mut self, pos: usize) {
+        *self |= 1 << pos;
+    }
+
+    fn multiply(&self, other: &Self) -> Self::Product {
+        *self as usize * *other as usize
+    }
+}
+
+macro_rules! impl_wide_diagnostic_bits {
+    ($($int:ty),*) => {
+        $(
+            impl DiagnosticBits for $int {
+                type Product = num_bigint::BigUint;
+
+                fn zero(_width: usize) -> Self {
+                    0
+                }
+
+                fn bit(&self, pos: usize) -> bool {
+                    self & (1 << pos) != 0
+                }
+
+                fn set(&mut self, pos: usize) {
+                    *self |= 1 << pos;
+                }
+
+                fn multiply(&self, other: &Self) -> Self::Product {
+                    num_bigint::BigUint::from(*self) * *other
+                }
+            }
+        )*
+    };
+}
+
+impl_wide_diagnostic_bits!(u64, u128);
+
+/// Bits of numbers wider than any integer type, in words of 64 bits from the lowest one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitSet {
+    words: Vec<u64>,
+}
+
+impl DiagnosticBits for BitSet {
+    type Product = num_bigint::BigUint;
+
+    fn zero(width: usize) -> Self {
+        Self { words: vec![0; width.div_ceil(64)] }
+    }
+
+    fn bit(&self, pos: usize) -> bool {
+        self.words[pos / 64] & (1 << (pos % 64)) != 0
+    }
+
+    fn set(&mut self, pos: usize) {
+        self.words[pos / 64] |= 1 << (pos % 64);
+    }
+
+    fn multiply(&self, other: &Self) -> Self::Product {
+        let to_biguint = |bits: &BitSet| {
+            let bytes: Vec<u8> = bits.words.iter().flat_map(|word| word.to_le_bytes()).collect();
+            num_bigint::BigUint::from_bytes_le(&bytes)
+        };
+
+        to_biguint(self) * to_biguint(other)
+    }
+}
+
+/// Parsed diagnostic report with the number of bits each of its numbers is written with.
+#[derive(Clone)]
+pub struct PuzzleInput<B = PuzzleBinary> {
+    pub input: Vec<B>,
     pub binary_size: usize,
 }
 
-pub fn read_input_to_vec<S: AsRef<str>>(input: impl IntoIterator<Item = std::io::Result<S>>) -> anyhow::Result<PuzzleInput> {
-    let mut input = input.into_iter().peekable();
-    let line_length = {
-        let peek = input.peek();
-        match peek {
-            Some(result) => result.as_ref().map_err(|e| anyhow::anyhow!("{}", e))?.as_ref().len(),
-            None => return Err(anyhow::anyhow!("No lines to read")),
+/// A diagnostic report stored in the narrowest type its numbers fit in.
+#[derive(Clone)]
+pub enum Diagnostic {
+    Narrow(PuzzleInput<PuzzleBinary>),
+    Wide(PuzzleInput<u64>),
+    Wider(PuzzleInput<u128>),
+    Any(PuzzleInput<BitSet>),
+}
+
+impl Diagnostic {
+    pub fn power_consumption(self) -> Answer {
+        match self {
+            Diagnostic::Narrow(puzzle) => run_binary_diagnostic(puzzle).into(),
+            Diagnostic::Wide(puzzle) => run_binary_diagnostic(puzzle).into(),
+            Diagnostic::Wider(puzzle) => run_binary_diagnostic(puzzle).into(),
+            Diagnostic::Any(puzzle) => run_binary_diagnostic(puzzle).into(),
         }
-    };
+    }
+
+    pub fn life_support_rating(self) -> anyhow::Result<Answer> {
+        Ok(match self {
+            Diagnostic::Narrow(puzzle) => run_life_support_rating(puzzle)?.into(),
+            Diagnostic::Wide(puzzle) => run_life_support_rating(puzzle)?.into(),
+            Diagnostic::Wider(puzzle) => run_life_support_rating(puzzle)?.into(),
+            Diagnostic::Any(puzzle) => run_life_support_rating(puzzle)?.into(),
+        })
+    }
+}
+
+fn first_line_length<S: AsRef<str>, I: Iterator<Item = std::io::Result<S>>>(input: &mut std::iter::Peekable<I>) -> anyhow::Result<usize> {
+    match input.peek() {
+        Some(result) => Ok(result.as_ref().map_err(|e| std::io::Error::new(e.kind(), e.to_string()))?.as_ref().len()),
+        None => Err(ParseError("No lines to read".to_string()).into()),
+    }
+}
+
+fn read_numbers<B: DiagnosticBits, S: AsRef<str>>(input: impl Iterator<Item = std::io::Result<S>>, line_length: usize) -> anyhow::Result<PuzzleInput<B>> {
+    let numbers = input
+        .enumerate()
+        .map(|(line_index, line)| {
+            let line = line?;
+            let line = line.as_ref();
+            if line.len() != line_length {
+                return Err(ParseError(format!("Line {} has {} bits, but the first line has {}", line_index + 1, line.len(), line_length)).into());
+            }
 
-    let vec_result: std::io::Result<Vec<PuzzleBinary>> = input.into_iter()
-        .map(|line| {
-            let value = line?.as_ref().chars().enumerate().fold(0, |acc: PuzzleBinary, (i, c)| {
+            let mut value = B::zero(line_length);
+            for (i, c) in line.chars().enumerate() {
                 if c == '1' {
-                    acc | 1 << (line_length - i - 1)
+                    value.set(line_length - i - 1);
                 }
-                else {
-                    acc
-                }
-            });
+            }
 
             Ok(value)
-        }).collect();
-    
-    
-    Ok(PuzzleInput { input: vec_result?, binary_size: line_length })
+        })
+        .collect::<anyhow::Result<Vec<B>>>()?;
+
+    Ok(PuzzleInput { input: numbers, binary_size: line_length })
+}
+
+/// Parses a diagnostic report of binary numbers of equal length, up to the width of [`PuzzleBinary`].
+pub fn read_input_to_vec<S: AsRef<str>>(input: impl IntoIterator<Item = std::io::Result<S>>) -> anyhow::Result<PuzzleInput> {
+    let mut input = input.into_iter().peekable();
+    let line_length = first_line_length(&mut input)?;
+    if line_length > PuzzleBinary::BITS as usize {
+        return Err(ParseError(format!("Numbers of {} bits don't fit in {} bits", line_length, PuzzleBinary::BITS)).into());
+    }
+
+    read_numbers(input, line_length)
+}
+
+/// Parses a diagnostic report of binary numbers of equal length and any width.
+pub fn read_diagnostic<S: AsRef<str>>(input: impl IntoIterator<Item = std::io::Result<S>>) -> anyhow::Result<Diagnostic> {
+    let mut input = input.into_iter().peekable();
+    let line_length = first_line_length(&mut input)?;
+
+    Ok(match line_length {
+        0..=32 => Diagnostic::Narrow(read_numbers(input, line_length)?),
+        33..=64 => Diagnostic::Wide(read_numbers(input, line_length)?),
+        65..=128 => Diagnostic::Wider(read_numbers(input, line_length)?),
+        _ => Diagnostic::Any(read_numbers(input, line_length)?),
+    })
 }
 
-fn count_ones<'a>(input: impl IntoIterator<Item = &'a PuzzleBinary>, pos: usize) -> usize{
-    input.into_iter().filter(|&number| number & (1 << pos) != 0).count()
+fn count_ones<'a, B: DiagnosticBits + 'a>(input: impl IntoIterator<Item = &'a B>, pos: usize) -> usize {
+    input.into_iter().filter(|number| number.bit(pos)).count()
 }
 
-pub fn run_binary_diagnostic(puzzle: PuzzleInput) -> usize {
-    let gamma = (0..puzzle.binary_size)
-        .map(|i| {
-            // Add up all the ones at the position i
-            let ones_count = count_ones(&puzzle.input, i);
+/// Returns the power consumption, the product of the gamma and epsilon rates.
+pub fn run_binary_diagnostic<B: DiagnosticBits>(puzzle: PuzzleInput<B>) -> B::Product {
+    let mut gamma = B::zero(puzzle.binary_size);
+    let mut epsilon = B::zero(puzzle.binary_size);
 
-            // If the bit is the majority then gamma has a bit set on i position
-            // Also note to self: multiplication is faster than division
-            if ones_count * 2 > puzzle.input.len()
-               { 1 << i } else { 0 }
-        })
-        .fold(0, |acc, bit| { acc | bit });
+    for i in 0..puzzle.binary_size {
+        // Add up all the ones at the position i
+        let ones_count = count_ones(&puzzle.input, i);
 
-    // Mask to ignore bits from negation
-    let mask = (1 << puzzle.binary_size) - 1;
+        // If the bit is the majority then gamma has a bit set on i position, otherwise epsilon has
+        // Also note to self: multiplication is faster than division
+        if ones_count * 2 > puzzle.input.len() {
+            gamma.set(i);
+        }
+        else {
+            epsilon.set(i);
+        }
+    }
 
-    gamma * (mask & !gamma)
+    gamma.multiply(&epsilon)
 }
 
-pub fn run_life_support_rating(puzzle: PuzzleInput) -> anyhow::Result<PuzzleBinary> {
-    let mut oxygen: PuzzleBinary = 0;
-    let mut co2: PuzzleBinary = 0;
+/// Returns the life support rating, the product of the oxygen generator and CO2 scrubber ratings.
+pub fn run_life_support_rating<B: DiagnosticBits>(puzzle: PuzzleInput<B>) -> anyhow::Result<B::Product> {
+    let mut oxygen = B::zero(puzzle.binary_size);
+    let mut co2 = B::zero(puzzle.binary_size);
 
     let mut current = puzzle.input.clone();
     for i in (0..puzzle.binary_size).rev() {
         let ones_count = count_ones(&current, i);
-        // Guartneed by Rust to be 1
-        let oxygen_criteria = (ones_count * 2 >= current.len()) as PuzzleBinary;
+        let oxygen_criteria = ones_count * 2 >= current.len();
         
-        current.retain(|&num| (num >> i) & 1 == oxygen_criteria);
+        current.retain(|num| num.bit(i) == oxygen_criteria);
 
         if current.len() < 2 {
-            oxygen = *current.get(0).ok_or(anyhow::anyhow!("There was no valid number filtering by oxygen criteria"))?;
+            oxygen = current.first().ok_or(anyhow::anyhow!("There was no valid number filtering by oxygen criteria"))?.clone();
             break;
         }
     }
@@ -123,24 +279,27 @@ pub fn run_life_support_rating(puzzle: PuzzleInput) -> anyhow::Result<PuzzleBina
     let mut current = puzzle.input;
     for i in (0..puzzle.binary_size).rev() {
         let ones_count = count_ones(&current, i);
-        let co2_criteria = (ones_count * 2 < current.len()) as PuzzleBinary;
+        let co2_criteria = ones_count * 2 < current.len();
         
-        current.retain(|&num| (num >> i) & 1 == co2_criteria);
+        current.retain(|num| num.bit(i) == co2_criteria);
 
         if current.len() < 2 {
-            co2 = *current.get(0).ok_or(anyhow::anyhow!("There was no valid number filtering by co2 criteria"))?;
+            co2 = current.first().ok_or(anyhow::anyhow!("There was no valid number filtering by co2 criteria"))?.clone();
             break;
         }
     }
 
-    Ok(co2 * oxygen)
+    Ok(co2.multiply(&oxygen))
 }
 
 #[cfg(test)]
+#[allow(clippy::redundant_closure)]
 mod tests {
     use crate::day_3::run_life_support_rating;
     use crate::day_3::read_input_to_vec;
     use crate::day_3::run_binary_diagnostic;
+    use crate::day_3::read_diagnostic;
+    use crate::output::Answer;
 
     const DIAGNOSTIC_REPORT_EXAMPLE: &str = "\
         00100\n\
@@ -171,4 +330,25 @@ mod tests {
 
         assert_eq!(result, 230);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn it_reads_diagnostics_of_any_width() {
+        // Repeating every number keeps the most common bits, and the ratings are found in the first repetition
+        for repeat in [1, 10, 20, 30] {
+            let report: Vec<String> = DIAGNOSTIC_REPORT_EXAMPLE.split('\n').map(|line| line.repeat(repeat)).collect();
+            let rating = |bits: &str| num_bigint::BigUint::parse_bytes(bits.repeat(repeat).as_bytes(), 2).unwrap();
+            let expected = |product: num_bigint::BigUint| match repeat {
+                1 => Answer::Integer(product.try_into().unwrap()),
+                _ => Answer::BigInteger(product),
+            };
+
+            let diagnostic = read_diagnostic(report.iter().map(Ok)).unwrap();
+
+            assert_eq!(diagnostic.clone().power_consumption(), expected(rating("10110") * rating("01001")));
+            assert_eq!(diagnostic.life_support_rating().unwrap(), expected(rating("10111") * rating("01010")));
+        }
+
+        assert!(read_input_to_vec(["1".repeat(33)].iter().map(Ok)).is_err());
+        assert!(read_diagnostic(["101", "1011"].iter().map(Ok)).is_err());
+    }
+}
@@ -2,12 +2,85 @@ pub mod cli {
     use crate::day_4::run_losing_board;
     use crate::day_4::run_giant_squid;
     use crate::day_4::read_puzzle_input;
-    use crate::command_line::read_input_from_matches;
+    use crate::day_4::play_game;
+    use crate::day_4::rank_cards;
+    use crate::day_4::fastest_win;
+    use crate::day_4::generate_puzzle_input;
+    use crate::day_4::replay;
+    use crate::day_4::simulate_games;
+    use crate::day_4::PuzzleInput;
+    use crate::day_4::Tournament;
+    use crate::day_4::BingoValue;
+    use crate::day_4::WinPattern;
+    use crate::command_line::expect_submatches;
+    use crate::command_line::get_input_source;
+    use crate::command_line::parse_arg;
+    use crate::command_line::parse_optional_arg;
     use crate::command_line::ChallengeSolutionArgs;
+    use crate::command_line::PreparedSolution;
+    use crate::core::input::InputLines;
+    use crate::core::input::InputSource;
+    use crate::error::UsageError;
+    use crate::output::Answer;
     use anyhow::Result;
 
     const GIANT_SQUID_COMMAND: &str = "4_1";
     const LOSING_BOARD_COMMAND: &str = "4_2";
+    const REPLAY_COMMAND: &str = "4_replay";
+    const SIMULATE_COMMAND: &str = "4_simulate";
+    const RIG_COMMAND: &str = "4_rig";
+    const GENERATE_COMMAND: &str = "4_generate";
+
+    fn add_win_arg(subcommand: clap::App<'_>) -> clap::App<'_> {
+        subcommand
+            .arg(clap::Arg::new("win").long("win").takes_value(true).value_name("PATTERN").default_value("lines")
+                .help(WinPattern::HELP))
+    }
+
+    fn add_game_args(subcommand: clap::App<'_>) -> clap::App<'_> {
+        add_win_arg(subcommand)
+            .arg(clap::Arg::new("ranking").long("ranking")
+                .help("Outputs every card in the order they won, with the draw, the winning line and the score"))
+            .arg(clap::Arg::new("layout").long("layout").takes_value(true).possible_values(["cards", "index"]).default_value("cards")
+                .help("Cards crossing every drawn number themselves, or an index of the cards with each number, faster with many cards"))
+    }
+
+    fn uses_index(matches: &clap::ArgMatches, subcommand: &'static str) -> Result<bool> {
+        let submatches = expect_submatches(matches, subcommand);
+        let index = submatches.value_of("layout") == Some("index");
+        if index && submatches.is_present("ranking") {
+            return Err(UsageError("The ranking is only made with the cards layout".to_string()).into());
+        }
+
+        Ok(index)
+    }
+
+    fn run_ranking(puzzle: PuzzleInput) -> Answer {
+        let bingos = play_game(puzzle);
+        let lines: Vec<String> = std::iter::once("Rank\tCard\tDraws\tNumber\tLine\tScore".to_string())
+            .chain(rank_cards(&bingos).into_iter().enumerate().map(|(rank, (card, bingo))| match bingo {
+                Some(bingo) => format!("{}\t{}\t{}\t{}\t{}\t{}", rank + 1, card + 1, bingo.draw + 1, bingo.value, bingo.line, bingo.score),
+                None => format!("-\t{}\t-\t-\tnever won\t-", card + 1),
+            }))
+            .collect();
+
+        Answer::Text(lines.join("\n"))
+    }
+
+    // Hands out copies of the game to play. The first one is copied while preparing, so that
+    // the solution times only the game
+    fn fresh_games(puzzle: PuzzleInput) -> impl Fn() -> PuzzleInput {
+        let first = std::cell::RefCell::new(Some(puzzle.clone()));
+        move || first.borrow_mut().take().unwrap_or_else(|| puzzle.clone())
+    }
+
+    fn prepare_game(input: InputLines, matches: &clap::ArgMatches, subcommand: &'static str) -> Result<PuzzleInput> {
+        let pattern: WinPattern = parse_arg(matches, subcommand, "win")?;
+        let mut puzzle = read_puzzle_input(input)?;
+        puzzle.set_win_pattern(&pattern)?;
+
+        Ok(puzzle)
+    }
 
     pub struct GiantSquid { }
 
@@ -16,13 +89,26 @@ pub mod cli {
             GIANT_SQUID_COMMAND
         }
 
-        fn run(&mut self, matches: &clap::ArgMatches) -> Result<String> { 
-            let input = read_input_from_matches(self, matches)?;
-            let puzzle = read_puzzle_input(input)?;
+        fn configure<'a>(&self, subcommand: clap::App<'a>) -> clap::App<'a> {
+            add_game_args(subcommand)
+        }
+
+        fn prepare(&mut self, input: InputLines, matches: &clap::ArgMatches) -> Result<PreparedSolution> { 
+            let puzzle = prepare_game(input, matches, GIANT_SQUID_COMMAND)?;
+            if uses_index(matches, GIANT_SQUID_COMMAND)? {
+                let tournament = Tournament::new(&puzzle)?;
+                return Ok(Box::new(move || Ok(tournament.first_winner()?.into())));
+            }
+            let game = fresh_games(puzzle);
+            if expect_submatches(matches, GIANT_SQUID_COMMAND).is_present("ranking") {
+                return Ok(Box::new(move || Ok(run_ranking(game()))));
+            }
             
-            let answer = run_giant_squid(puzzle);
+            Ok(Box::new(move || {
+                let answer = run_giant_squid(game())?;
 
-            Ok(format!("Answer is: {}", answer))
+                Ok(answer.into())
+            }))
         }
     }
 
@@ -33,169 +119,1134 @@ pub mod cli {
             LOSING_BOARD_COMMAND
         }
 
-        fn run(&mut self, matches: &clap::ArgMatches) -> Result<String> { 
-            let input = read_input_from_matches(self, matches)?;
-            let puzzle = read_puzzle_input(input)?;
+        fn configure<'a>(&self, subcommand: clap::App<'a>) -> clap::App<'a> {
+            add_game_args(subcommand)
+        }
+
+        fn prepare(&mut self, input: InputLines, matches: &clap::ArgMatches) -> Result<PreparedSolution> { 
+            let puzzle = prepare_game(input, matches, LOSING_BOARD_COMMAND)?;
+            if uses_index(matches, LOSING_BOARD_COMMAND)? {
+                let tournament = Tournament::new(&puzzle)?;
+                return Ok(Box::new(move || Ok(tournament.last_winner()?.into())));
+            }
+            let game = fresh_games(puzzle);
+            if expect_submatches(matches, LOSING_BOARD_COMMAND).is_present("ranking") {
+                return Ok(Box::new(move || Ok(run_ranking(game()))));
+            }
             
-            let answer = run_losing_board(puzzle);
+            Ok(Box::new(move || {
+                let answer = run_losing_board(game())?;
+
+                Ok(answer.into())
+            }))
+        }
+    }
+
+    pub struct BingoReplay { }
+
+    impl ChallengeSolutionArgs for BingoReplay {
+        fn get_subcommand(&self) -> &'static str {
+            REPLAY_COMMAND
+        }
+
+        fn configure<'a>(&self, subcommand: clap::App<'a>) -> clap::App<'a> {
+            add_win_arg(subcommand)
+                .about("Shows the cards after every draw, waiting for Enter or for a delay between draws")
+                .arg(clap::Arg::new("delay").long("delay").takes_value(true).value_name("MS")
+                    .help("Goes to the next draw after MS milliseconds instead of waiting for Enter"))
+                .arg(clap::Arg::new("no-color").long("no-color")
+                    .help("Marks drawn numbers with brackets only, without ANSI colors"))
+        }
+
+        fn runs_in_batch(&self) -> bool {
+            false
+        }
+
+        fn prepare(&mut self, input: InputLines, matches: &clap::ArgMatches) -> Result<PreparedSolution> {
+            let delay: Option<u64> = parse_optional_arg(matches, REPLAY_COMMAND, "delay")?;
+            let color = !expect_submatches(matches, REPLAY_COMMAND).is_present("no-color");
+            if delay.is_none() && matches!(get_input_source(expect_submatches(matches, REPLAY_COMMAND), REPLAY_COMMAND), InputSource::Stdin) {
+                return Err(UsageError("Stepping with Enter needs stdin, so an input read from stdin needs --delay".to_string()).into());
+            }
+
+            let puzzle = prepare_game(input, matches, REPLAY_COMMAND)?;
+
+            Ok(Box::new(move || {
+                let mut next_draw = || -> Result<bool> {
+                    match delay {
+                        Some(delay) => {
+                            std::thread::sleep(std::time::Duration::from_millis(delay));
+                            Ok(true)
+                        },
+                        None => {
+                            // The prompt goes to stderr, so the frames can be redirected on their own
+                            eprintln!("\nPress Enter for the next draw, or q and Enter to stop");
+                            let mut line = String::new();
+                            let read = std::io::stdin().read_line(&mut line)?;
+                            Ok(read > 0 && line.trim() != "q")
+                        },
+                    }
+                };
+
+                let bingos = replay(puzzle.clone(), &mut std::io::stdout(), color, &mut next_draw)?;
+                let summary = match rank_cards(&bingos).first() {
+                    Some((card, Some(bingo))) => format!("Card {} won first on draw {} with a score of {}", card + 1, bingo.draw + 1, bingo.score),
+                    _ => "No card won".to_string(),
+                };
+
+                Ok(Answer::Text(summary))
+            }))
+        }
+    }
+
+    pub struct BingoSimulation { }
+
+    impl ChallengeSolutionArgs for BingoSimulation {
+        fn get_subcommand(&self) -> &'static str {
+            SIMULATE_COMMAND
+        }
+
+        fn configure<'a>(&self, subcommand: clap::App<'a>) -> clap::App<'a> {
+            add_win_arg(subcommand)
+                .about("Plays many games with the drawn numbers shuffled, reporting how often each card wins first")
+                .arg(clap::Arg::new("trials").short('n').long("trials").takes_value(true).default_value("10000")
+                    .help("Number of games played"))
+                .arg(clap::Arg::new("seed").long("seed").takes_value(true).default_value("2021")
+                    .help("Seed of the random generator shuffling the draws, the same seed gives the same results"))
+        }
+
+        fn runs_in_batch(&self) -> bool {
+            false
+        }
+
+        fn prepare(&mut self, input: InputLines, matches: &clap::ArgMatches) -> Result<PreparedSolution> {
+            let trials: usize = parse_arg(matches, SIMULATE_COMMAND, "trials")?;
+            let seed: u64 = parse_arg(matches, SIMULATE_COMMAND, "seed")?;
+            let puzzle = prepare_game(input, matches, SIMULATE_COMMAND)?;
+
+            Ok(Box::new(move || Ok(Answer::Text(simulate_games(&puzzle, trials, seed).to_string()))))
+        }
+    }
+
+    pub struct BingoRig { }
+
+    impl ChallengeSolutionArgs for BingoRig {
+        fn get_subcommand(&self) -> &'static str {
+            RIG_COMMAND
+        }
+
+        fn configure<'a>(&self, subcommand: clap::App<'a>) -> clap::App<'a> {
+            add_win_arg(subcommand)
+                .about("Finds the shortest draws of the drawn numbers making a card win before all others")
+                .arg(clap::Arg::new("card").long("card").takes_value(true).value_name("N")
+                    .help("Card to make win, counting from 1 [default: every card]"))
+        }
+
+        fn runs_in_batch(&self) -> bool {
+            false
+        }
+
+        fn prepare(&mut self, input: InputLines, matches: &clap::ArgMatches) -> Result<PreparedSolution> {
+            let card: Option<usize> = parse_optional_arg(matches, RIG_COMMAND, "card")?;
+            let puzzle = prepare_game(input, matches, RIG_COMMAND)?;
+
+            let targets: Vec<usize> = match card {
+                Some(0) => return Err(UsageError("Cards are counted from 1".to_string()).into()),
+                Some(card) => vec![card - 1],
+                None => (0..puzzle.cards.len()).collect(),
+            };
+
+            Ok(Box::new(move || {
+                let lines = targets.iter()
+                    .map(|&target| match fastest_win(&puzzle, target) {
+                        Ok(rigged) => Ok(format!("Card {} wins first with its {} after {} draws, scoring {}: {}",
+                            target + 1, rigged.bingo.line, rigged.draws.len(), rigged.bingo.score,
+                            rigged.draws.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(","))),
+                        Err(err) if err.is::<crate::day_4::Unwinnable>() && targets.len() > 1 => Ok(err.to_string()),
+                        Err(err) => Err(err),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                Ok(Answer::Text(lines.join("\n")))
+            }))
+        }
+    }
+
+    pub struct BingoGenerator { }
+
+    impl ChallengeSolutionArgs for BingoGenerator {
+        fn get_subcommand(&self) -> &'static str {
+            GENERATE_COMMAND
+        }
+
+        fn configure<'a>(&self, subcommand: clap::App<'a>) -> clap::App<'a> {
+            subcommand
+                .about("Outputs a random input with many cards, e.g. to compare the layouts with \
+                    'bench --variant \"--layout cards\" --variant \"--layout index\" -- 4_1 -i FILE'")
+                .arg(clap::Arg::new("cards").long("cards").takes_value(true).default_value("1000")
+                    .help("Number of cards"))
+                .arg(clap::Arg::new("width").long("width").takes_value(true).default_value("5"))
+                .arg(clap::Arg::new("height").long("height").takes_value(true).default_value("5"))
+                .arg(clap::Arg::new("numbers").long("numbers").takes_value(true).default_value("100")
+                    .help("Numbers are drawn from 0 up to, but excluding this one"))
+                .arg(clap::Arg::new("seed").long("seed").takes_value(true).default_value("2021"))
+        }
+
+        fn runs_in_batch(&self) -> bool {
+            false
+        }
+
+        // Generating doesn't need an input, so none is read
+        fn run(&mut self, matches: &clap::ArgMatches) -> Result<Answer> {
+            let solution = self.prepare(Box::new(std::iter::empty()), matches)?;
+
+            solution()
+        }
+
+        fn prepare(&mut self, _input: InputLines, matches: &clap::ArgMatches) -> Result<PreparedSolution> {
+            let cards: usize = parse_arg(matches, GENERATE_COMMAND, "cards")?;
+            let width: usize = parse_arg(matches, GENERATE_COMMAND, "width")?;
+            let height: usize = parse_arg(matches, GENERATE_COMMAND, "height")?;
+            let numbers: BingoValue = parse_arg(matches, GENERATE_COMMAND, "numbers")?;
+            let seed: u64 = parse_arg(matches, GENERATE_COMMAND, "seed")?;
+
+            Ok(Box::new(move || Ok(Answer::Text(generate_puzzle_input(cards, width, height, numbers, seed)?.trim_end().to_string()))))
+        }
+    }
+}
+
+use crate::error::ParseError;
+use crate::error::UsageError;
+use std::sync::Arc;
+
+/// A number on a bingo card or a drawn one.
+pub type BingoValue = u32;
+/// The sum of the uncrossed numbers of a winning card times the number that made it win.
+pub type BingoScore = u64;
+
+/// The sets of cells that make a card win once all of them are crossed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WinPattern {
+    // Any full row or column, as in the puzzle
+    Lines,
+    // Any full row or column, or either diagonal of a square card
+    Diagonals,
+    Corners,
+    // Every number of the card
+    Blackout,
+    // Marked cells of the card, listed row by row
+    Mask(Vec<Vec<bool>>),
+}
+
+impl WinPattern {
+    pub const HELP: &'static str = "'lines', 'diagonals', 'corners', 'blackout', or a mask with rows separated by '/' \
+        and cells marked with 'x', e.g. 'x...x/.x.x./..x../.x.x./x...x'";
+
+    // Returns the sets of cells of a card of the given shape that win
+    fn lines(&self, width: usize, height: usize) -> anyhow::Result<Vec<WinLine>> {
+        let rows = (0..height).map(|row| (format!("row {}", row + 1), (0..width).map(|column| (column, row)).collect()));
+        let columns = (0..width).map(|column| (format!("column {}", column + 1), (0..height).map(|row| (column, row)).collect()));
+
+        match self {
+            WinPattern::Lines => Ok(rows.chain(columns).collect()),
+            WinPattern::Diagonals if width != height => Err(UsageError(format!("Diagonals need square cards, but they are {}x{}", width, height)).into()),
+            WinPattern::Diagonals => Ok(rows.chain(columns)
+                .chain(std::iter::once(("diagonal".to_string(), (0..width).map(|i| (i, i)).collect())))
+                .chain(std::iter::once(("anti-diagonal".to_string(), (0..width).map(|i| (width - 1 - i, i)).collect())))
+                .collect()),
+            WinPattern::Corners => {
+                let mut corners = vec![(0, 0), (width - 1, 0), (0, height - 1), (width - 1, height - 1)];
+                corners.sort_unstable();
+                corners.dedup();
+
+                Ok(vec![("corners".to_string(), corners)])
+            },
+            WinPattern::Blackout => Ok(vec![("blackout".to_string(), (0..height).flat_map(|row| (0..width).map(move |column| (column, row))).collect())]),
+            WinPattern::Mask(mask) => {
+                if mask.len() != height || mask.iter().any(|row| row.len() != width) {
+                    return Err(UsageError(format!("The mask doesn't have the {}x{} cells of the cards", width, height)).into());
+                }
+
+                let cells = (0..height).flat_map(|row| (0..width).map(move |column| (column, row)))
+                    .filter(|&(column, row)| mask[row][column])
+                    .collect();
+
+                Ok(vec![("mask".to_string(), cells)])
+            },
+        }
+    }
+}
 
-            Ok(format!("Answer is: {}", answer))
+impl std::str::FromStr for WinPattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lines" => Ok(WinPattern::Lines),
+            "diagonals" => Ok(WinPattern::Diagonals),
+            "corners" => Ok(WinPattern::Corners),
+            "blackout" => Ok(WinPattern::Blackout),
+            // A single word that isn't a mask is most likely a misspelled pattern name
+            word if !word.contains('/') && word.chars().any(|c| c.is_alphabetic() && c != 'x' && c != 'X') => {
+                Err(anyhow::anyhow!("Unknown win pattern '{}', expected {}", word, Self::HELP))
+            },
+            mask => {
+                let rows = mask.split('/')
+                    .map(|row| row.chars().map(|cell| match cell {
+                        'x' | 'X' | '#' | '1' => Ok(true),
+                        '.' | '0' => Ok(false),
+                        other => Err(anyhow::anyhow!("Unknown cell '{}' in the mask", other)),
+                    }).collect::<anyhow::Result<Vec<_>>>())
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+
+                match rows.iter().flatten().any(|&marked| marked) {
+                    true => Ok(WinPattern::Mask(rows)),
+                    false => Err(anyhow::anyhow!("The mask '{}' has no marked cells", mask)),
+                }
+            },
         }
     }
 }
 
-const CARD_WIDTH: usize = 5;
-const CARD_HEIGHT: usize = 5;
+// A named set of cells, as their column and row positions
+type WinLine = (String, Vec<(usize, usize)>);
+
+// The winning sets of cells of a pattern, shared by all cards of the same shape
+#[derive(Debug)]
+struct CardLayout {
+    width: usize,
+    height: usize,
+    names: Vec<String>,
+    sizes: Vec<usize>,
+    // Indices of the winning sets each cell is part of, row by row
+    cell_lines: Vec<Vec<usize>>,
+}
+
+impl CardLayout {
+    fn new(pattern: &WinPattern, width: usize, height: usize) -> anyhow::Result<Self> {
+        let lines = pattern.lines(width, height)?;
+        let mut cell_lines = vec![Vec::new(); width * height];
+
+        for (i, (_, cells)) in lines.iter().enumerate() {
+            for (column, row) in cells {
+                cell_lines[row * width + column].push(i);
+            }
+        }
+
+        Ok(Self {
+            width,
+            height,
+            sizes: lines.iter().map(|(_, cells)| cells.len()).collect(),
+            names: lines.into_iter().map(|(name, _)| name).collect(),
+            cell_lines,
+        })
+    }
+}
 
+/// A bingo card of any size tracking the numbers that are still uncrossed.
+#[derive(Debug, Clone)]
 pub struct BingoCard {
-    uncrossed_sum: u32,
-    // Association of a value on a bingo card to a tuple of its column and row positions
-    value_map: std::collections::HashMap<u8, (usize, usize), ahash::RandomState>,
-    uncrossed_columns: [u8; 5],
-    uncrossed_rows: [u8; 5],
+    uncrossed_sum: BingoScore,
+    values: Vec<BingoValue>,
+    // Association of an uncrossed value on a bingo card to the column and row positions of
+    // every cell it is in
+    value_map: std::collections::HashMap<BingoValue, Vec<(usize, usize)>, ahash::RandomState>,
+    layout: Arc<CardLayout>,
+    // Number of uncrossed cells in each winning set of the layout
+    uncrossed_lines: Vec<usize>,
 }
 
 impl BingoCard {
-    fn new(values: &[u8; 25]) -> BingoCard {
+    // Creates a card from its values listed row by row
+    fn new(values: &[BingoValue], layout: Arc<CardLayout>) -> BingoCard {
         let mut card = BingoCard {
             uncrossed_sum: 0,
+            values: values.to_vec(),
             value_map: std::collections::HashMap::default(),
-            uncrossed_columns: [5; 5],
-            uncrossed_rows: [5; 5]
+            uncrossed_lines: layout.sizes.clone(),
+            layout,
         };
 
         for (i, val) in values.iter().enumerate() {
-            card.uncrossed_sum += *val as u32;
-            let (column, row) = (i % CARD_HEIGHT, i / CARD_WIDTH);
-            card.value_map.insert(*val, (column, row));
+            card.uncrossed_sum += *val as BingoScore;
+            let (column, row) = (i % card.layout.width, i / card.layout.width);
+            card.value_map.entry(*val).or_default().push((column, row));
         }
 
         card
     }
 
-    // Returns score if a bingo is found
-    fn cross_value(&mut self, value: u8) -> Option<u32> {
-        let (column, row) = {
-            match self.value_map.get(&value) {
-                Some(value_pos) => *value_pos,
-                None => return None,
+    // Returns the index of the completed winning set and the score if a bingo is found
+    fn cross_value(&mut self, value: BingoValue) -> Option<(usize, BingoScore)> {
+        // Crossed values are removed, so drawing a number again doesn't change anything
+        let positions = self.value_map.remove(&value)?;
+        let mut completed = None;
+
+        for (column, row) in positions {
+            self.uncrossed_sum -= value as BingoScore;
+
+            for &line in &self.layout.cell_lines[row * self.layout.width + column] {
+                self.uncrossed_lines[line] -= 1;
+                if self.uncrossed_lines[line] == 0 {
+                    completed = completed.or(Some(line));
+                }
             }
-        };
+        }
+
+        completed.map(|line| (line, self.calculate_bingo(value)))
+    }
+
+    // Returns the values of the card row by row
+    fn values(&self) -> &[BingoValue] {
+        &self.values
+    }
 
-        self.uncrossed_sum -= value as u32;
-        
-        self.uncrossed_columns[column] -= 1;
-        self.uncrossed_rows[row] -= 1;
+    // Returns the uncrossed values that are in more than one cell, with their number of cells
+    fn duplicates(&self) -> Vec<(BingoValue, usize)> {
+        let mut duplicates: Vec<(BingoValue, usize)> = self.value_map.iter()
+            .filter(|(_, positions)| positions.len() > 1)
+            .map(|(value, positions)| (*value, positions.len()))
+            .collect();
+        duplicates.sort_unstable();
 
-        if self.uncrossed_columns[column] == 0 || self.uncrossed_rows[row] == 0 {
-            return Some(self.calculate_bingo(value as u32));
+        duplicates
+    }
+
+    // Returns the values in each winning set of the layout
+    fn winning_sets(&self) -> Vec<std::collections::BTreeSet<BingoValue>> {
+        let mut sets = vec![std::collections::BTreeSet::new(); self.layout.sizes.len()];
+        for (lines, value) in self.layout.cell_lines.iter().zip(&self.values) {
+            for &line in lines {
+                sets[line].insert(*value);
+            }
         }
 
-        return None;
+        sets
+    }
+
+    // Returns the index of the winning set with the fewest uncrossed cells and their number
+    fn closest_line(&self) -> (usize, usize) {
+        self.uncrossed_lines.iter().copied().enumerate()
+            .min_by_key(|&(_, uncrossed)| uncrossed)
+            .expect("Cards have at least one winning set")
+    }
+
+    fn calculate_bingo(&self, value: BingoValue) -> BingoScore {
+        self.uncrossed_sum * value as BingoScore
     }
 
-    fn calculate_bingo(&self, value: u32) -> u32 {
-        self.uncrossed_sum * value
+    // Makes the card win with the sets of cells of another layout, before any number is crossed
+    fn set_layout(&mut self, layout: Arc<CardLayout>) {
+        self.uncrossed_lines = layout.sizes.clone();
+        self.layout = layout;
     }
 }
 
+/// Drawn numbers and the bingo cards playing the game.
+#[derive(Debug, Clone)]
 pub struct PuzzleInput {
-    values: Vec<u8>,
+    values: Vec<BingoValue>,
     cards: Vec<BingoCard>,
 }
 
+impl PuzzleInput {
+    /// Changes the sets of cells that make the cards win.
+    pub fn set_win_pattern(&mut self, pattern: &WinPattern) -> anyhow::Result<()> {
+        if let Some(card) = self.cards.first() {
+            let layout = Arc::new(CardLayout::new(pattern, card.layout.width, card.layout.height)?);
+            self.cards.iter_mut().for_each(|card| card.set_layout(layout.clone()));
+        }
+
+        Ok(())
+    }
+}
+
+// Collects the rows of cards, checking that every card has the shape of the first one
 struct BingoCardAcc {
     vec: Vec<BingoCard>,
-    tmp_values: [u8; 25],
-    i: usize
+    // Width and height of the first card
+    shape: Option<(usize, usize)>,
+    tmp_values: Vec<BingoValue>,
+    width: usize,
+    first_line: usize,
 }
 
 impl BingoCardAcc {
     fn new() -> Self {
-        return Self {
+        Self {
             vec: Vec::new(),
-            tmp_values: [0; 25],
-            i: 0
+            shape: None,
+            tmp_values: Vec::new(),
+            width: 0,
+            first_line: 0,
         }
     }
 
-    fn flush_values(&mut self) {
-        if self.i != 0 {
-            self.vec.push(BingoCard::new(&self.tmp_values));
-            self.i = 0;
+    fn flush_values(&mut self, line_number: usize) -> anyhow::Result<()> {
+        if self.tmp_values.is_empty() {
+            return Ok(());
         }
-    } 
 
-    fn add_row(&mut self, row: Vec<u8>) {
-        self.tmp_values[self.i*CARD_WIDTH..self.i*CARD_WIDTH+CARD_WIDTH].copy_from_slice(&row);
-        self.i += 1;
+        let shape = (self.width, self.tmp_values.len() / self.width);
+        match self.shape {
+            Some(expected) if expected != shape => return Err(ParseError(format!(
+                "The card on lines {} to {} has {}x{} numbers, but the first card has {}x{}",
+                self.first_line, line_number - 1, shape.0, shape.1, expected.0, expected.1)).into()),
+            _ => self.shape = Some(shape),
+        }
+
+        let layout = match self.vec.last() {
+            Some(card) => card.layout.clone(),
+            None => Arc::new(CardLayout::new(&WinPattern::Lines, shape.0, shape.1)?),
+        };
+
+        let card = BingoCard::new(&self.tmp_values, layout);
+        for (value, count) in card.duplicates() {
+            log::warn!("The card on lines {} to {} has the number {} {} times, all of them are crossed together",
+                self.first_line, line_number - 1, value, count);
+        }
+
+        self.vec.push(card);
+        self.tmp_values.clear();
+
+        Ok(())
+    }
+
+    fn add_row(&mut self, row: Vec<BingoValue>, line_number: usize) -> anyhow::Result<()> {
+        if self.tmp_values.is_empty() {
+            self.width = row.len();
+            self.first_line = line_number;
+        } else if row.len() != self.width {
+            return Err(ParseError(format!("Line {} has {} numbers, but the rows above it have {}", line_number, row.len(), self.width)).into());
+        }
+
+        self.tmp_values.extend(row);
+
+        Ok(())
     }
 }
 
+/// Parses the drawn numbers from the first line followed by the bingo cards separated by empty
+/// lines. Cards can have any number of rows and columns, as long as all of them have the same.
+/// They win with full rows or columns until [`PuzzleInput::set_win_pattern`] is called.
 pub fn read_puzzle_input<S: AsRef<str>>(input: impl IntoIterator<Item = std::io::Result<S>>) -> anyhow::Result<PuzzleInput>  {
     use anyhow::Context;
 
     let mut input_iter = input.into_iter();
 
-    let values: Vec<u8> = {
+    let values: Vec<BingoValue> = {
         let first_line = input_iter.next()
-            .ok_or(anyhow::anyhow!("No input was given"))?.with_context(|| "Failed to read a first line")?;
+            .ok_or_else(|| ParseError("No input was given".to_string()))?.with_context(|| "Failed to read a first line")?;
 
-        first_line.as_ref().split(',')
-            .map(|substring| substring.parse::<u8>())
-            .collect::<Result<Vec<_>, _>>()? // One day this will by try_collect(). Hopefully
+        first_line.as_ref().trim().split(',')
+            .map(|substring| substring.trim().parse::<BingoValue>())
+            .collect::<Result<Vec<_>, _>>() // One day this will by try_collect(). Hopefully
+            .with_context(|| "Invalid drawn number on line 1")?
     };
 
-    let mut card_acc: BingoCardAcc = input_iter.fold(Ok(BingoCardAcc::new()), |acc_res: anyhow::Result<BingoCardAcc>, result| {
-        let mut acc = acc_res?;
+    let mut line_number = 1;
+    let mut card_acc: BingoCardAcc = input_iter.try_fold(BingoCardAcc::new(), |mut acc: BingoCardAcc, result| -> anyhow::Result<BingoCardAcc> {
         let asref = result?;
-        let line: &str = asref.as_ref();
+        let line: &str = asref.as_ref().trim();
+        line_number += 1;
 
         if !line.is_empty() {
-            let row = line.split(' ')
-                .filter(|word| !word.is_empty())
-                .map(|substring| substring.parse::<u8>())
-                .collect::<Result<Vec<_>, _>>()?;
+            let row = line.split_whitespace()
+                .map(|substring| substring.parse::<BingoValue>())
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("Invalid number on line {}", line_number))?;
 
-            acc.add_row(row);
+            acc.add_row(row, line_number)?;
         } else {
-            acc.flush_values();
+            acc.flush_values(line_number)?;
         }
 
         Ok(acc)
     })?;
 
-    card_acc.flush_values();
+    card_acc.flush_values(line_number + 1)?;
 
-    Ok(PuzzleInput { values: values, cards: card_acc.vec })
+    Ok(PuzzleInput { values, cards: card_acc.vec })
 }
 
-fn run_giant_squid(input: PuzzleInput) -> u32 {
-    let values = input.values;
+/// How and when a card won.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bingo {
+    // Index of the draw that made the card win, from 0
+    pub draw: usize,
+    pub value: BingoValue,
+    // The completed set of cells, e.g. 'row 2' or 'diagonal'
+    pub line: String,
+    pub score: BingoScore,
+}
+
+/// Draws every number and returns how each card won, in the order of the cards, or `None` for
+/// the cards that never won.
+pub fn play_game(input: PuzzleInput) -> Vec<Option<Bingo>> {
     let mut cards = input.cards;
+    let mut bingos = vec![None; cards.len()];
 
-    let answer = values.into_iter().find_map(|value| {
-        cards.iter_mut().find_map(|card| card.cross_value(value))
-    }).unwrap();
+    for (draw, &value) in input.values.iter().enumerate() {
+        draw_value(&mut cards, &mut bingos, draw, value);
+    }
+
+    bingos
+}
+
+// Crosses a drawn value on the cards that didn't win yet, recording the new bingos
+fn draw_value(cards: &mut [BingoCard], bingos: &mut [Option<Bingo>], draw: usize, value: BingoValue) {
+    for (card, bingo) in cards.iter_mut().zip(bingos.iter_mut()).filter(|(_, bingo)| bingo.is_none()) {
+        if let Some((line, score)) = card.cross_value(value) {
+            *bingo = Some(Bingo { draw, value, line: card.layout.names[line].clone(), score });
+        }
+    }
+}
+
+// Number of cards drawn next to each other by the replay
+const REPLAY_CARDS_PER_ROW: usize = 4;
+
+// Wraps the text in an ANSI style like '1;32', unless colors are off or the style is empty
+fn paint(text: &str, ansi_code: &str, color: bool) -> String {
+    match color && !ansi_code.is_empty() {
+        true => format!("\x1b[{}m{}\x1b[0m", ansi_code, text),
+        false => text.to_string(),
+    }
+}
+
+/// Draws the cards after the draw of index `draw`, highlighting the drawn numbers and the cards
+/// that won. Without colors, drawn numbers are put in brackets.
+pub fn render_cards(cards: &[BingoCard], values: &[BingoValue], draw: usize, bingos: &[Option<Bingo>], color: bool) -> String {
+    let drawn: std::collections::HashSet<BingoValue, ahash::RandomState> = values[..=draw].iter().copied().collect();
+    let number_width = cards.iter().flat_map(|card| card.values()).max().map_or(1, |value| value.to_string().len());
+
+    // Lines of every card along with their width on the screen, which excludes color codes
+    let blocks: Vec<Vec<(String, usize)>> = cards.iter().zip(bingos).enumerate()
+        .map(|(index, (card, bingo))| {
+            let grid: Vec<(String, usize)> = card.values().chunks(card.layout.width)
+                .map(|row| {
+                    let cells: String = row.iter().map(|value| {
+                        let number = format!("{:>width$}", value, width = number_width);
+                        match drawn.contains(value) {
+                            false => format!(" {} ", number),
+                            true if *value == values[draw] => paint(&format!("[{}]", number), "1;30;43", color),
+                            true => paint(&format!("[{}]", number), "1;32", color),
+                        }
+                    }).collect();
+
+                    (cells, row.len() * (number_width + 2))
+                })
+                .collect();
+
+            let (header, footer, style) = match bingo {
+                Some(bingo) if bingo.draw == draw => (format!("Card {} BINGO!", index + 1), format!("{}, score {}", bingo.line, bingo.score), "1;32"),
+                Some(bingo) => (format!("Card {} won", index + 1), format!("{}, score {}", bingo.line, bingo.score), "2"),
+                None => (format!("Card {}", index + 1), String::new(), ""),
+            };
+
+            std::iter::once((paint(&header, style, color), header.len()))
+                .chain(grid)
+                .chain(std::iter::once((paint(&footer, style, color), footer.len())))
+                .collect()
+        })
+        .collect();
+
+    let mut text = format!("Draw {} of {}: {}\n", draw + 1, values.len(), values[draw]);
+    for row in blocks.chunks(REPLAY_CARDS_PER_ROW) {
+        let widths: Vec<usize> = row.iter().map(|block| block.iter().map(|(_, width)| *width).max().unwrap_or(0)).collect();
+
+        for line in 0..row[0].len() {
+            let cells: Vec<String> = row.iter().zip(&widths)
+                .map(|(block, width)| format!("{}{}", block[line].0, " ".repeat(width - block[line].1)))
+                .collect();
+            text.push('\n');
+            text.push_str(cells.join("   ").trim_end());
+        }
+        text.push('\n');
+    }
+
+    text
+}
+
+/// Plays the game drawing the cards after every draw on `out`. `next_draw` is called between
+/// draws and stops the replay by returning false. Returns the bingos as [`play_game`].
+pub fn replay(input: PuzzleInput, out: &mut impl std::io::Write, color: bool, next_draw: &mut dyn FnMut() -> anyhow::Result<bool>) -> anyhow::Result<Vec<Option<Bingo>>> {
+    let mut cards = input.cards;
+    let mut bingos = vec![None; cards.len()];
+
+    for (draw, &value) in input.values.iter().enumerate() {
+        draw_value(&mut cards, &mut bingos, draw, value);
+
+        if color {
+            // Clears the screen and moves the cursor to the top
+            write!(out, "\x1b[2J\x1b[H")?;
+        } else if draw > 0 {
+            writeln!(out)?;
+        }
+        write!(out, "{}", render_cards(&cards, &input.values, draw, &bingos, color))?;
+        out.flush()?;
+
+        if draw + 1 < input.values.len() && !next_draw()? {
+            break;
+        }
+    }
+
+    Ok(bingos)
+}
+
+/// Lists the cards from the first to win to the last, followed by the ones that never won. Cards
+/// winning on the same draw are in the order of the input.
+pub fn rank_cards(bingos: &[Option<Bingo>]) -> Vec<(usize, Option<&Bingo>)> {
+    let mut ranking: Vec<(usize, Option<&Bingo>)> = bingos.iter().map(Option::as_ref).enumerate().collect();
+    ranking.sort_by_key(|(card, bingo)| (bingo.map_or(usize::MAX, |bingo| bingo.draw), *card));
+
+    ranking
+}
+
+/// Outcomes of games played with the drawn numbers in random orders, by [`simulate_games`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulationReport {
+    pub trials: usize,
+    pub seed: u64,
+    // Number of games won first by each card alone
+    pub wins: Vec<usize>,
+    // Number of games each card won first together with other cards on the same draw
+    pub ties: Vec<usize>,
+    pub tied_games: usize,
+    pub no_winner: usize,
+    // Number of games for each number of draws made until the first bingo
+    pub draws: std::collections::BTreeMap<usize, usize>,
+}
+
+impl SimulationReport {
+    /// Returns the share of the games the card won first, without another card winning on the same draw.
+    pub fn win_probability(&self, card: usize) -> f64 {
+        self.wins[card] as f64 / self.trials.max(1) as f64
+    }
+
+    /// Returns the share of the games the card won first together with other cards.
+    pub fn tie_probability(&self, card: usize) -> f64 {
+        self.ties[card] as f64 / self.trials.max(1) as f64
+    }
+
+    /// Returns the mean number of draws until the first bingo in the games that had one.
+    pub fn mean_draws(&self) -> Option<f64> {
+        let games: usize = self.draws.values().sum();
+        let total: usize = self.draws.iter().map(|(draws, count)| draws * count).sum();
+
+        (games > 0).then(|| total as f64 / games as f64)
+    }
+}
+
+impl std::fmt::Display for SimulationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} games with seed {}", self.trials, self.seed)?;
+        writeln!(f, "\nCard\tWins\tProbability\tTies\tTie probability")?;
+        for card in 0..self.wins.len() {
+            writeln!(f, "{}\t{}\t{:.4}\t{}\t{:.4}", card + 1, self.wins[card], self.win_probability(card), self.ties[card], self.tie_probability(card))?;
+        }
+        if self.tied_games > 0 {
+            writeln!(f, "Tied\t{}\t{:.4}", self.tied_games, self.tied_games as f64 / self.trials.max(1) as f64)?;
+        }
+        if self.no_winner > 0 {
+            writeln!(f, "None\t{}\t{:.4}", self.no_winner, self.no_winner as f64 / self.trials.max(1) as f64)?;
+        }
+
+        writeln!(f, "\nDraws\tGames\tShare")?;
+        for (draws, games) in &self.draws {
+            writeln!(f, "{}\t{}\t{:.4}", draws, games, *games as f64 / self.trials.max(1) as f64)?;
+        }
+
+        match self.mean_draws() {
+            Some(mean) => write!(f, "\nMean draws until the first bingo: {:.2}", mean),
+            None => write!(f, "\nNo game had a bingo"),
+        }
+    }
+}
+
+/// Plays `trials` games with the drawn numbers shuffled by a generator seeded with `seed`,
+/// counting which card wins first and after how many draws. Cards winning on the same draw
+/// are counted as ties rather than wins.
+pub fn simulate_games(input: &PuzzleInput, trials: usize, seed: u64) -> SimulationReport {
+    let mut rng = crate::core::random::SplitMix64::new(seed);
+    let mut values = input.values.clone();
+    let mut report = SimulationReport {
+        trials,
+        seed,
+        wins: vec![0; input.cards.len()],
+        ties: vec![0; input.cards.len()],
+        tied_games: 0,
+        no_winner: 0,
+        draws: std::collections::BTreeMap::new(),
+    };
+
+    for _ in 0..trials {
+        rng.shuffle(&mut values);
+        let mut cards = input.cards.clone();
+
+        // Every card is crossed, so all of the cards completing on the first winning draw are found
+        let first = values.iter().enumerate().find_map(|(draw, &value)| {
+            let winners: Vec<usize> = cards.iter_mut().enumerate()
+                .filter_map(|(card, bingo_card)| bingo_card.cross_value(value).map(|_| card))
+                .collect();
+
+            (!winners.is_empty()).then_some((draw, winners))
+        });
+
+        match first {
+            Some((draw, winners)) => {
+                match winners[..] {
+                    [card] => report.wins[card] += 1,
+                    _ => {
+                        report.tied_games += 1;
+                        winners.iter().for_each(|&card| report.ties[card] += 1);
+                    },
+                }
+                *report.draws.entry(draw + 1).or_insert(0) += 1;
+            },
+            None => report.no_winner += 1,
+        }
+    }
+
+    report
+}
+
+/// The cards of a game indexed by their numbers, for games with many cards. A drawn number only
+/// touches the cards it is on, and the crossed cells of a card are bits of a mask, so full rows,
+/// columns or other winning sets are found by comparing masks.
+pub struct Tournament {
+    values: Vec<BingoValue>,
+    // Cards with each number, along with the mask of the cells of the number on the card
+    index: std::collections::HashMap<BingoValue, Vec<(usize, u64)>, ahash::RandomState>,
+    line_masks: Vec<u64>,
+    line_names: Vec<String>,
+    sums: Vec<BingoScore>,
+}
+
+// Cells crossed on every card of a tournament when its game stopped, and the cards that won
+struct TournamentState {
+    crossed: Vec<u64>,
+    won: Vec<bool>,
+}
+
+// How a card of a tournament won: the draw, the index of the completed line and the score
+struct TournamentBingo {
+    draw: usize,
+    line: usize,
+    score: BingoScore,
+}
+
+impl Tournament {
+    pub const MAX_CELLS: usize = 64;
+
+    pub fn new(input: &PuzzleInput) -> anyhow::Result<Self> {
+        let layout = match input.cards.first() {
+            Some(card) => card.layout.clone(),
+            None => Arc::new(CardLayout::new(&WinPattern::Lines, 0, 0)?),
+        };
+        if layout.width * layout.height > Self::MAX_CELLS {
+            return Err(UsageError(format!("Indexed cards can have at most {} numbers, but they have {}x{}", Self::MAX_CELLS, layout.width, layout.height)).into());
+        }
+
+        let mut line_masks = vec![0; layout.sizes.len()];
+        for (cell, lines) in layout.cell_lines.iter().enumerate() {
+            for &line in lines {
+                line_masks[line] |= 1 << cell;
+            }
+        }
+
+        let mut index: std::collections::HashMap<BingoValue, Vec<(usize, u64)>, ahash::RandomState> = Default::default();
+        for (card_index, card) in input.cards.iter().enumerate() {
+            for (&value, positions) in &card.value_map {
+                let mask = positions.iter().fold(0, |mask, (column, row)| mask | 1 << (row * layout.width + column));
+                index.entry(value).or_default().push((card_index, mask));
+            }
+        }
+        // Cards are kept in the order of the input, which decides between cards winning on the same draw
+        index.values_mut().for_each(|cards| cards.sort_unstable_by_key(|(card, _)| *card));
+
+        Ok(Self {
+            values: input.values.clone(),
+            index,
+            line_masks,
+            line_names: layout.names.clone(),
+            sums: input.cards.iter().map(|card| card.uncrossed_sum).collect(),
+        })
+    }
+
+    // Plays the game calling `on_bingo` with the index of every card that wins and how it won, in
+    // the order they win, until it returns false. Returns the state the game stopped in.
+    fn play(&self, on_bingo: &mut dyn FnMut(usize, TournamentBingo) -> bool) -> TournamentState {
+        let mut crossed = vec![0u64; self.sums.len()];
+        let mut sums = self.sums.clone();
+        let mut won = vec![false; self.sums.len()];
+
+        for (draw, value) in self.values.iter().enumerate() {
+            for &(card, mask) in self.index.get(value).map_or(&[][..], Vec::as_slice) {
+                // Skips the cards that already won or had this number drawn before
+                if won[card] || crossed[card] & mask == mask {
+                    continue;
+                }
+
+                crossed[card] |= mask;
+                sums[card] -= *value as BingoScore * mask.count_ones() as BingoScore;
+
+                if let Some(line) = self.line_masks.iter().position(|&line| line & mask != 0 && crossed[card] & line == line) {
+                    won[card] = true;
+                    if !on_bingo(card, TournamentBingo { draw, line, score: sums[card] * *value as BingoScore }) {
+                        return TournamentState { crossed, won };
+                    }
+                }
+            }
+        }
+
+        TournamentState { crossed, won }
+    }
+
+    // Reports the cards that never won with the winning sets they miss the fewest numbers of
+    fn no_winner(&self, state: &TournamentState) -> NoWinner {
+        let closest = state.crossed.iter().zip(&state.won).enumerate()
+            .filter(|(_, (_, &won))| !won)
+            .map(|(card, (&crossed, _))| {
+                let (line, missing) = self.line_masks.iter()
+                    .map(|&line| (line & !crossed).count_ones() as usize)
+                    .enumerate()
+                    .min_by_key(|&(_, missing)| missing)
+                    .expect("Cards have at least one winning set");
+
+                (card, missing, self.line_names[line].clone())
+            })
+            .collect();
+
+        NoWinner::from_closest(closest, self.values.len(), self.sums.len())
+    }
+
+    /// Same as [`run_giant_squid`].
+    pub fn first_winner(&self) -> Result<BingoScore, NoWinner> {
+        let mut first = None;
+        let state = self.play(&mut |_, bingo| {
+            first = Some(bingo.score);
+            false
+        });
+
+        first.ok_or_else(|| self.no_winner(&state))
+    }
+
+    /// Same as [`run_losing_board`].
+    pub fn last_winner(&self) -> Result<BingoScore, NoWinner> {
+        let mut last = None;
+        let state = self.play(&mut |_, bingo| {
+            last = Some(bingo.score);
+            true
+        });
+
+        match state.won.iter().all(|&won| won) {
+            true => last.ok_or_else(|| self.no_winner(&state)),
+            false => Err(self.no_winner(&state)),
+        }
+    }
+
+    /// Same as [`play_game`].
+    pub fn play_game(&self) -> Vec<Option<Bingo>> {
+        let mut bingos = vec![None; self.sums.len()];
+        self.play(&mut |card, bingo| {
+            let value = self.values[bingo.draw];
+            bingos[card] = Some(Bingo { draw: bingo.draw, value, line: self.line_names[bingo.line].clone(), score: bingo.score });
+            true
+        });
+
+        bingos
+    }
+}
+
+/// Returns a puzzle input with `cards` cards of `width` by `height` different numbers below
+/// `numbers`, drawn in a random order. The same seed gives the same input.
+pub fn generate_puzzle_input(cards: usize, width: usize, height: usize, numbers: BingoValue, seed: u64) -> anyhow::Result<String> {
+    use std::fmt::Write;
+
+    if width == 0 || height == 0 || ((width * height) as u64) > numbers as u64 {
+        return Err(UsageError(format!("Cards of {}x{} different numbers can't be made of {} numbers", width, height, numbers)).into());
+    }
+
+    let mut rng = crate::core::random::SplitMix64::new(seed);
+    let mut pool: Vec<BingoValue> = (0..numbers).collect();
+    let number_width = (numbers - 1).to_string().len();
+
+    rng.shuffle(&mut pool);
+    let mut text = pool.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(",");
+    text.push('\n');
+
+    for _ in 0..cards {
+        // A partial shuffle puts random numbers at the front of the pool
+        for i in 0..width * height {
+            let j = i + rng.below((pool.len() - i) as u64) as usize;
+            pool.swap(i, j);
+        }
+
+        text.push('\n');
+        for row in pool[..width * height].chunks(width) {
+            let cells: Vec<String> = row.iter().map(|value| format!("{:>width$}", value, width = number_width)).collect();
+            writeln!(text, "{}", cells.join(" "))?;
+        }
+    }
+
+    Ok(text)
+}
+
+/// Draws making a card win before every other one, found by [`fastest_win`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RiggedGame {
+    pub card: usize,
+    pub bingo: Bingo,
+    pub draws: Vec<BingoValue>,
+}
+
+/// No draws of the available numbers make the card win before every other one. Has the reason
+/// for each winning set of the card.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unwinnable {
+    pub card: usize,
+    pub reasons: Vec<String>,
+}
+
+impl std::fmt::Display for Unwinnable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Card {} can't win first: {}", self.card + 1, self.reasons.join("; "))
+    }
+}
+
+impl std::error::Error for Unwinnable {}
+
+/// Finds the shortest sequence of the drawn numbers that makes the card of index `target` win
+/// before every other card, counting a bingo of another card on the same draw as a loss.
+///
+/// Drawing the numbers of one winning set of the target is always the shortest way, and the
+/// order doesn't matter: another card completes one of its sets along the way exactly when all
+/// of its numbers are in the drawn ones. So the smallest set with no other card's set inside
+/// wins, and when every set has one, no sequence can make the target win first.
+pub fn fastest_win(input: &PuzzleInput, target: usize) -> anyhow::Result<RiggedGame> {
+    let card = input.cards.get(target).ok_or_else(|| UsageError(format!("There is no card {}", target + 1)))?;
+    let available: std::collections::BTreeSet<BingoValue> = input.values.iter().copied().collect();
+    let others: Vec<(usize, Vec<std::collections::BTreeSet<BingoValue>>)> = input.cards.iter().enumerate()
+        .filter(|(index, _)| *index != target)
+        .map(|(index, card)| (index, card.winning_sets()))
+        .collect();
+
+    let mut reasons = Vec::new();
+    let mut best: Option<std::collections::BTreeSet<BingoValue>> = None;
+
+    for (line, set) in card.winning_sets().into_iter().enumerate() {
+        let name = &card.layout.names[line];
+
+        if let Some(missing) = set.iter().find(|value| !available.contains(value)) {
+            reasons.push(format!("{} needs {}, which is never drawn", name, missing));
+            continue;
+        }
+
+        let blocker = others.iter()
+            .find_map(|(index, sets)| sets.iter().position(|other| other.is_subset(&set)).map(|other| (index, other)));
+        match blocker {
+            Some((index, other)) => reasons.push(format!("{} also completes the {} of card {}", name, input.cards[*index].layout.names[other], index + 1)),
+            None if best.as_ref().is_none_or(|best| set.len() < best.len()) => best = Some(set),
+            None => {},
+        }
+    }
+
+    let draws: Vec<BingoValue> = best.ok_or(Unwinnable { card: target, reasons })?.into_iter().collect();
+
+    // Plays the rigged game to make sure the target is the only winner, on the last draw
+    let bingos = play_game(PuzzleInput { values: draws.clone(), cards: input.cards.clone() });
+    let winners: Vec<usize> = bingos.iter().enumerate().filter(|(_, bingo)| bingo.is_some()).map(|(index, _)| index).collect();
+    match (winners.as_slice(), &bingos[target]) {
+        ([winner], Some(bingo)) if *winner == target && bingo.draw + 1 == draws.len() => Ok(RiggedGame { card: target, bingo: bingo.clone(), draws }),
+        _ => Err(anyhow::anyhow!("The draws {:?} didn't make card {} the only winner", draws, target + 1)),
+    }
+}
+
+/// The draws ran out before the expected cards won.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoWinner {
+    pub draws: usize,
+    pub cards: usize,
+    pub winners: usize,
+    // Cards that didn't win with the fewest numbers missing for a win, along with the missing
+    // numbers and the winning set of cells they are in
+    pub closest: Vec<(usize, usize, String)>,
+}
+
+impl NoWinner {
+    // How many of the closest cards are reported
+    const CLOSEST_COUNT: usize = 3;
+
+    fn new<'a>(losers: impl Iterator<Item = (usize, &'a BingoCard)>, draws: usize, cards: usize) -> Self {
+        let closest: Vec<(usize, usize, String)> = losers
+            .map(|(index, card)| {
+                let (line, missing) = card.closest_line();
+                (index, missing, card.layout.names[line].clone())
+            })
+            .collect();
+
+        Self::from_closest(closest, draws, cards)
+    }
+
+    // Keeps the closest of every card that didn't win
+    fn from_closest(mut closest: Vec<(usize, usize, String)>, draws: usize, cards: usize) -> Self {
+        closest.sort_by_key(|&(index, missing, _)| (missing, index));
+        let winners = cards - closest.len();
+        closest.truncate(Self::CLOSEST_COUNT);
 
-    return answer;
+        Self { draws, cards, winners, closest }
+    }
+}
+
+impl std::fmt::Display for NoWinner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.winners {
+            0 => write!(f, "No card won after {} draws", self.draws)?,
+            winners => write!(f, "{} of {} cards never won after {} draws", self.cards - winners, self.cards, self.draws)?,
+        }
+
+        if !self.closest.is_empty() {
+            let closest: Vec<String> = self.closest.iter()
+                .map(|(index, missing, line)| format!("card {} misses {} of {}", index + 1, missing, line))
+                .collect();
+            write!(f, ". Closest: {}", closest.join(", "))?;
+        }
+
+        Ok(())
+    }
 }
 
-pub fn run_losing_board(input: PuzzleInput) -> u32 {
-    use retain_mut::RetainMut;
+impl std::error::Error for NoWinner {}
 
+/// Returns the score of the first board to win.
+pub fn run_giant_squid(input: PuzzleInput) -> Result<BingoScore, NoWinner> {
     let values = input.values;
     let mut cards = input.cards;
-    let mut scores: Vec<u32> = Vec::new();
+    let draws = values.len();
+
+    let answer = values.into_iter().find_map(|value| {
+        cards.iter_mut().find_map(|card| card.cross_value(value)).map(|(_, score)| score)
+    });
+
+    answer.ok_or_else(|| NoWinner::new(cards.iter().enumerate(), draws, cards.len()))
+}
+
+/// Returns the score of the last board to win.
+pub fn run_losing_board(input: PuzzleInput) -> Result<BingoScore, NoWinner> {
+    let values = input.values;
+    let card_count = input.cards.len();
+    let mut cards: Vec<(usize, BingoCard)> = input.cards.into_iter().enumerate().collect();
+    let mut scores: Vec<BingoScore> = Vec::new();
+    let draws = values.len();
 
     let answer = values.into_iter().find_map(|value| {
         scores.clear();
 
-        cards.retain_mut(|card| {
+        cards.retain_mut(|(_, card)| {
             match card.cross_value(value) {
                 None => true,
-                Some(bingo) => {
-                    scores.push(bingo);
+                Some((_, score)) => {
+                    scores.push(score);
                     false
                 }
             }
@@ -203,20 +1254,32 @@ pub fn run_losing_board(input: PuzzleInput) -> u32 {
 
         match cards.is_empty() {
             false => None,
-            true => {
-                Some(*scores.last().unwrap())
-            }
+            true => scores.last().copied(),
         }
-    }).unwrap();
+    });
 
-    answer
+    answer.ok_or_else(|| NoWinner::new(cards.iter().map(|(index, card)| (*index, card)), draws, card_count))
 }
 
 #[cfg(test)]
+#[allow(clippy::redundant_closure)]
 mod test {
     use crate::day_4::run_losing_board;
     use crate::day_4::run_giant_squid;
+    use crate::day_4::fastest_win;
+    use crate::day_4::generate_puzzle_input;
+    use crate::day_4::play_game;
+    use crate::day_4::rank_cards;
     use crate::day_4::read_puzzle_input;
+    use crate::day_4::replay;
+    use crate::day_4::simulate_games;
+    use crate::day_4::Bingo;
+    use crate::day_4::NoWinner;
+    use crate::day_4::PuzzleInput;
+    use crate::day_4::Tournament;
+    use crate::day_4::Unwinnable;
+    use crate::day_4::WinPattern;
+    use crate::command_line::parse_arg;
 
     const EXAMPLE: &str = "7,4,9,5,11,17,23,2,0,14,21,24,10,16,13,6,15,25,12,22,18,20,8,19,3,26,1\n\
 \n\
@@ -241,14 +1304,220 @@ mod test {
     #[test]
     fn it_passes_giant_squid_example() {
         let puzzle_input = read_puzzle_input(EXAMPLE.split('\n').map(|s| Ok(s))).unwrap();
-        let answer = run_giant_squid(puzzle_input);
+        let answer = run_giant_squid(puzzle_input).unwrap();
         assert_eq!(answer, 4512);
     }
 
     #[test]
     fn it_passes_losing_board_example() {
         let puzzle_input = read_puzzle_input(EXAMPLE.split('\n').map(|s| Ok(s))).unwrap();
-        let answer = run_losing_board(puzzle_input);
+        let answer = run_losing_board(puzzle_input).unwrap();
         assert_eq!(answer, 1924);
     }
+
+    #[test]
+    fn it_plays_with_cards_of_any_size() {
+        let input = "1000,7,300,1\r\n\r\n300 1 1000\r\n4 5 6\r\n\r\n7 2 9\r\n300 1000 1";
+        let puzzle_input = read_puzzle_input(input.split('\n').map(Ok)).unwrap();
+
+        // The second card wins its first column with 300, and the first card its first row with 1
+        assert_eq!(run_giant_squid(puzzle_input.clone()).unwrap(), (2 + 9 + 1) * 300);
+        assert_eq!(run_losing_board(puzzle_input).unwrap(), 4 + 5 + 6);
+    }
+
+    #[test]
+    fn it_rejects_ragged_cards() {
+        let ragged = "1,2\n\n1 2 3\n4 5\n";
+        let mismatched = "1,2\n\n1 2\n3 4\n\n1 2 3\n4 5 6";
+
+        assert!(read_puzzle_input(ragged.split('\n').map(Ok)).unwrap_err().to_string().contains("Line 4 has 2 numbers"));
+        assert!(read_puzzle_input(mismatched.split('\n').map(Ok)).unwrap_err().to_string().contains("lines 6 to 7 has 3x2"));
+    }
+
+    #[test]
+    fn it_wins_with_other_patterns() {
+        let mut puzzle_input = read_puzzle_input(EXAMPLE.split('\n').map(Ok)).unwrap();
+
+        // The anti-diagonal 4, 9, 23, 11, 2 of the third card is full after the 8 first draws
+        puzzle_input.set_win_pattern(&"diagonals".parse().unwrap()).unwrap();
+        assert_eq!(run_giant_squid(puzzle_input.clone()).unwrap(), (325 - 7 - 4 - 9 - 5 - 11 - 17 - 23 - 2) * 2);
+
+        puzzle_input.set_win_pattern(&"blackout".parse().unwrap()).unwrap();
+        assert_eq!(run_giant_squid(puzzle_input.clone()).unwrap(), 0);
+
+        puzzle_input.set_win_pattern(&"x...x/...../...../...../x...x".parse().unwrap()).unwrap();
+        assert_eq!(run_giant_squid(puzzle_input.clone()).unwrap(), run_giant_squid({
+            let mut corners = puzzle_input.clone();
+            corners.set_win_pattern(&WinPattern::Corners).unwrap();
+            corners
+        }).unwrap());
+
+        assert!(puzzle_input.set_win_pattern(&"x.x/.x.".parse().unwrap()).is_err());
+        assert!("x.y".parse::<WinPattern>().is_err());
+    }
+
+    #[test]
+    fn it_ranks_cards_by_when_they_won() {
+        let puzzle_input = read_puzzle_input(EXAMPLE.split('\n').map(Ok)).unwrap();
+        let bingos = play_game(puzzle_input);
+
+        assert_eq!(bingos[2], Some(Bingo { draw: 11, value: 24, line: "row 1".to_string(), score: 4512 }));
+        assert_eq!(bingos[1].as_ref().map(|bingo| (bingo.draw, bingo.score)), Some((14, 1924)));
+        assert_eq!(rank_cards(&bingos).iter().map(|(card, _)| *card).collect::<Vec<_>>(), vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn it_reports_closest_cards_when_draws_run_out() {
+        let short_game = |draws: &str| read_puzzle_input(std::iter::once(draws).chain(EXAMPLE.split('\n').skip(1)).map(Ok)).unwrap();
+
+        let error: NoWinner = run_giant_squid(short_game("7,4,9,5,11,17,23,2,0,14,21")).unwrap_err();
+        assert_eq!(error.closest[0], (0, 1, "row 3".to_string()));
+        assert!(error.to_string().starts_with("No card won after 11 draws. Closest: card 1 misses 1 of row 3, card 3 misses 1 of row 1"));
+
+        let error = run_losing_board(short_game("7,4,9,5,11,17,23,2,0,14,21,24")).unwrap_err();
+        assert_eq!((error.cards, error.winners, error.closest.len()), (3, 1, 2));
+        assert!(error.to_string().starts_with("2 of 3 cards never won after 12 draws"));
+    }
+
+    #[test]
+    fn it_replays_draws_until_stopped() {
+        let puzzle_input = read_puzzle_input(EXAMPLE.split('\n').map(Ok)).unwrap();
+        let mut out = Vec::new();
+        let mut remaining = 12;
+
+        let bingos = replay(puzzle_input, &mut out, false, &mut || {
+            remaining -= 1;
+            Ok(remaining > 0)
+        }).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert_eq!(bingos.iter().flatten().count(), 1);
+        assert_eq!(text.matches("Draw ").count(), 12);
+        assert!(text.ends_with(&[
+            "Draw 12 of 27: 24",
+            "",
+            "Card 1                 Card 2                 Card 3 BINGO!",
+            " 22  13 [17][11][ 0]     3  15 [ 0][ 2] 22    [14][21][17][24][ 4]",
+            "  8 [ 2][23][ 4][24]   [ 9] 18  13 [17][ 5]    10  16  15 [ 9] 19",
+            "[21][ 9][14] 16 [ 7]    19   8 [ 7] 25 [23]    18   8 [23] 26  20",
+            "  6  10   3  18 [ 5]    20 [11] 10 [24][ 4]    22 [11] 13   6 [ 5]",
+            "  1  12  20  15  19    [14][21] 16  12   6    [ 2][ 0] 12   3 [ 7]",
+            "                                              row 1, score 4512",
+            "",
+        ].join("\n")));
+    }
+
+    #[test]
+    fn it_crosses_every_cell_of_duplicate_numbers() {
+        let puzzle_input = read_puzzle_input("1,5,5,2\n\n5 1\n5 2\n\n3 5\n4 6".split('\n').map(Ok)).unwrap();
+
+        assert_eq!(puzzle_input.cards[0].duplicates(), vec![(5, 2)]);
+        // Both cells of 5 are crossed at once, completing the first column
+        assert_eq!(run_giant_squid(puzzle_input.clone()).unwrap(), 2 * 5);
+        assert!(run_losing_board(puzzle_input).is_err());
+    }
+
+    #[test]
+    fn it_simulates_games_with_shuffled_draws() {
+        let puzzle_input = read_puzzle_input(EXAMPLE.split('\n').map(Ok)).unwrap();
+        let report = simulate_games(&puzzle_input, 500, 7);
+
+        assert_eq!(report, simulate_games(&puzzle_input, 500, 7));
+        assert_eq!(report.wins.iter().sum::<usize>() + report.tied_games + report.no_winner, 500);
+        assert_eq!(report.draws.values().sum::<usize>(), 500 - report.no_winner);
+        // A card needs at least 5 numbers for a bingo
+        assert!(report.draws.keys().all(|&draws| draws >= 5));
+        assert!(report.wins.iter().all(|&wins| wins > 0));
+
+        // Both cards have the same rows and columns, so they always win together
+        let tied = read_puzzle_input("1,2,3,4\n\n1 2\n3 4\n\n1 3\n2 4".split('\n').map(Ok)).unwrap();
+        let report = simulate_games(&tied, 50, 7);
+
+        assert_eq!(report.wins, vec![0, 0]);
+        assert_eq!(report.ties, vec![50, 50]);
+        assert_eq!(report.tied_games, 50);
+    }
+
+    #[test]
+    fn it_finds_shortest_draws_making_a_card_win_first() {
+        let puzzle_input = read_puzzle_input(EXAMPLE.split('\n').map(Ok)).unwrap();
+
+        for target in 0..3 {
+            let rigged = fastest_win(&puzzle_input, target).unwrap();
+            assert_eq!(rigged.draws.len(), 5);
+            assert_eq!(play_game(PuzzleInput { values: rigged.draws, cards: puzzle_input.cards.clone() })[target], Some(rigged.bingo));
+        }
+
+        // Without 4, the first card can only win with a row or a column of the second one
+        let shadowed = read_puzzle_input("1,2,3\n\n1 2\n3 4\n\n1 3\n2 5".split('\n').map(Ok)).unwrap();
+        let error = fastest_win(&shadowed, 0).unwrap_err().downcast::<Unwinnable>().unwrap();
+        assert_eq!(error.reasons, vec![
+            "row 1 also completes the column 1 of card 2",
+            "row 2 needs 4, which is never drawn",
+            "column 1 also completes the row 1 of card 2",
+            "column 2 needs 4, which is never drawn",
+        ]);
+    }
+
+    #[test]
+    fn it_explains_invalid_win_patterns() {
+        let app = clap::App::new("aoc2021").subcommand(clap::App::new("4_1").arg(clap::Arg::new("win").long("win").takes_value(true)));
+        let error_of = |pattern: &str| {
+            let matches = app.clone().get_matches_from(["aoc2021", "4_1", "--win", pattern]);
+            parse_arg::<WinPattern>(&matches, "4_1", "win").unwrap_err().to_string()
+        };
+
+        assert!(error_of("corner").starts_with("'corner' is not a valid value for --win: Unknown win pattern 'corner', expected 'lines'"));
+        assert!(error_of("x./.l").ends_with("Unknown cell 'l' in the mask"));
+        assert!(error_of("../..").ends_with("The mask '../..' has no marked cells"));
+    }
+
+    #[test]
+    fn it_shares_cards_across_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        assert_send_sync::<PuzzleInput>();
+    }
+
+    #[test]
+    fn it_finds_same_winners_with_indexed_cards() {
+        let generated = generate_puzzle_input(300, 5, 5, 75, 11).unwrap();
+        let duplicates = "1,5,5,2,3,4,6\n\n5 1\n5 2\n\n3 5\n4 6";
+
+        for (input, pattern) in [(EXAMPLE, "lines"), (EXAMPLE, "diagonals"), (generated.as_str(), "lines"), (generated.as_str(), "corners"), (duplicates, "lines")] {
+            let mut puzzle_input = read_puzzle_input(input.split('\n').map(Ok)).unwrap();
+            puzzle_input.set_win_pattern(&pattern.parse().unwrap()).unwrap();
+            let tournament = Tournament::new(&puzzle_input).unwrap();
+
+            assert_eq!(tournament.first_winner().ok(), run_giant_squid(puzzle_input.clone()).ok());
+            assert_eq!(tournament.last_winner().ok(), run_losing_board(puzzle_input).ok());
+        }
+
+        // The draws run out before anyone wins, or before the last card wins
+        for draws in ["7,4,9,5,11,17", "7,4,9,5,11,17,23,2,0,14,21,24,10,16"] {
+            let puzzle_input = read_puzzle_input(std::iter::once(draws).chain(EXAMPLE.split('\n').skip(1)).map(Ok)).unwrap();
+            let tournament = Tournament::new(&puzzle_input).unwrap();
+
+            assert_eq!(tournament.first_winner(), run_giant_squid(puzzle_input.clone()));
+            assert_eq!(tournament.last_winner(), run_losing_board(puzzle_input));
+        }
+    }
+
+    #[test]
+    fn it_ranks_same_cards_with_indexed_cards() {
+        let generated = generate_puzzle_input(300, 5, 5, 75, 11).unwrap();
+        // The last draws alone leave cards that never win, which are reported as the closest
+        let short_generated: String = generated.split(',').skip(60).collect::<Vec<_>>().join(",");
+        let short_example: String = std::iter::once("7,4,9,5,11,17,23,2,0,14,21,24,10,16").chain(EXAMPLE.split('\n').skip(1)).collect::<Vec<_>>().join("\n");
+
+        for input in [EXAMPLE, short_example.as_str(), generated.as_str(), short_generated.as_str()] {
+            let puzzle_input = read_puzzle_input(input.split('\n').map(Ok)).unwrap();
+            let tournament = Tournament::new(&puzzle_input).unwrap();
+            let bingos = tournament.play_game();
+            let expected = play_game(puzzle_input.clone());
+
+            assert_eq!(rank_cards(&bingos), rank_cards(&expected));
+            assert_eq!(tournament.last_winner(), run_losing_board(puzzle_input));
+        }
+    }
 }
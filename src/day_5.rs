@@ -1,11 +1,105 @@
 pub mod cli {
-    use crate::day_5::run_hydrotermal_diagonals;
+    use crate::command_line::expect_submatches;
+    use crate::command_line::parse_arg;
+    use crate::command_line::parse_optional_arg;
     use crate::command_line::ChallengeSolutionArgs;
-    use crate::day_5::run_hydrotermal_venture;
-    use crate::command_line::read_input_from_matches;
+    use crate::command_line::PreparedSolution;
+    use crate::core::input::InputLines;
+    use crate::day_5::count_at_least;
+    use crate::day_5::count_overlaps_by_intersection;
+    use crate::day_5::count_overlaps_on;
+    use crate::day_5::overlap_histogram_on;
+    use crate::day_5::read_segments;
+    use crate::day_5::render_on;
+    use crate::day_5::FloorKind;
+    use crate::day_5::Rasterization;
+    use crate::day_5::RenderFormat;
+    use crate::day_5::Segment;
+    use crate::day_5::VentCount;
+    use crate::error::UsageError;
+    use crate::output::Answer;
+    use std::io::Write;
 
     const HYDROTERMAL_VENTURE: &str = "5_1";
     const HYDRO_DIAGONAL: &str = "5_2";
+
+    fn add_floor_args(subcommand: clap::App<'_>) -> clap::App<'_> {
+        subcommand
+            .arg(clap::Arg::new("solver").long("solver").takes_value(true).possible_values(["grid", "intersections"]).default_value("grid")
+                .help("Draws the vents on a floor, or computes where segments overlap and cross without one"))
+            .arg(clap::Arg::new("floor").long("floor").takes_value(true).possible_values(FloorKind::VARIANTS).default_value("auto")
+                .help("Grid counting the vents: an array over the bounding box, a hash map of marked points, or dense when it fits"))
+            .arg(clap::Arg::new("raster").long("raster").takes_value(true).possible_values(Rasterization::VARIANTS).default_value("lattice")
+                .help("How segments at other angles than 45 degrees are drawn: through the points they cross exactly, or with Bresenham's algorithm"))
+            .arg(clap::Arg::new("threshold").long("threshold").takes_value(true).value_name("N").default_value("2")
+                .help("Counts the points crossed by at least N vents"))
+            .arg(clap::Arg::new("histogram").long("histogram")
+                .help("Outputs how many points are crossed by each number of vents"))
+            .arg(clap::Arg::new("render").long("render").takes_value(true).value_name("FORMAT").possible_values(RenderFormat::VARIANTS)
+                .help("Outputs the map of vents, cropped to the segments: the diagram from the puzzle, a grayscale or a heatmap image"))
+            .arg(clap::Arg::new("output").short('o').long("output").takes_value(true).value_name("FILE")
+                .help("File the rendered map is written to, required for images"))
+    }
+
+    fn prepare_render(segments: Vec<Segment>, floor: FloorKind, diagonals: bool, raster: Rasterization, format: RenderFormat, output: Option<String>) -> anyhow::Result<PreparedSolution> {
+        use anyhow::Context;
+
+        match (format, output) {
+            (RenderFormat::Ascii, None) => Ok(Box::new(move || {
+                let mut diagram = Vec::new();
+                render_on(floor, &segments, diagonals, raster, format, &mut diagram)?;
+
+                Ok(Answer::Text(String::from_utf8(diagram)?.trim_end().to_string()))
+            })),
+            (_, None) => Err(UsageError("Rendering an image requires a file given by --output".to_string()).into()),
+            (_, Some(path)) => Ok(Box::new(move || {
+                let mut file = std::io::BufWriter::new(std::fs::File::create(&path).with_context(|| format!("Failed to create '{}'", path))?);
+                let (width, height) = render_on(floor, &segments, diagonals, raster, format, &mut file)?;
+                file.flush()?;
+
+                Ok(Answer::Text(format!("Wrote {}x{} map to {}", width, height, path)))
+            })),
+        }
+    }
+
+    fn prepare_overlaps(input: InputLines, matches: &clap::ArgMatches, subcommand: &'static str, diagonals: bool) -> anyhow::Result<PreparedSolution> {
+        let floor: FloorKind = parse_arg(matches, subcommand, "floor")?;
+        let threshold: VentCount = parse_arg(matches, subcommand, "threshold")?;
+        let raster: Rasterization = parse_arg(matches, subcommand, "raster")?;
+        let histogram = expect_submatches(matches, subcommand).is_present("histogram");
+        let render: Option<RenderFormat> = parse_optional_arg(matches, subcommand, "render")?;
+        let output = expect_submatches(matches, subcommand).value_of("output").map(String::from);
+        let segments = read_segments(input)?;
+
+        if expect_submatches(matches, subcommand).value_of("solver") == Some("intersections") {
+            if histogram || render.is_some() || threshold != 2 {
+                return Err(UsageError("The intersections solver only counts points crossed by at least two vents".to_string()).into());
+            }
+
+            return Ok(Box::new(move || Ok(count_overlaps_by_intersection(&segments, diagonals)?.into())));
+        }
+
+        if let Some(format) = render {
+            return prepare_render(segments, floor, diagonals, raster, format, output);
+        }
+
+        if histogram {
+            return Ok(Box::new(move || {
+                let lines: Vec<String> = overlap_histogram_on(floor, &segments, diagonals, raster)?.into_iter()
+                    .map(|(vents, points)| format!("{}\t{}", vents, points))
+                    .collect();
+
+                Ok(Answer::Text(lines.join("\n")))
+            }));
+        }
+
+        match threshold {
+            // Overlaps of two vents are counted while drawing, without going over the floor again
+            2 => Ok(Box::new(move || Ok(count_overlaps_on(floor, &segments, diagonals, raster)?.into()))),
+            _ => Ok(Box::new(move || Ok(count_at_least(&overlap_histogram_on(floor, &segments, diagonals, raster)?, threshold).into()))),
+        }
+    }
+
     pub struct HydrotermalVenture { }
 
     impl ChallengeSolutionArgs for HydrotermalVenture {
@@ -13,12 +107,12 @@ pub mod cli {
             HYDROTERMAL_VENTURE
         }
 
-        fn run(&mut self, matches: &clap::ArgMatches) -> anyhow::Result<String> { 
-            let input = read_input_from_matches(self, matches)?;
-            
-            let answer = run_hydrotermal_venture(input);
+        fn configure<'a>(&self, subcommand: clap::App<'a>) -> clap::App<'a> {
+            add_floor_args(subcommand)
+        }
 
-            Ok(format!("Answer is: {}", answer?))
+        fn prepare(&mut self, input: InputLines, matches: &clap::ArgMatches) -> anyhow::Result<PreparedSolution> { 
+            prepare_overlaps(input, matches, HYDROTERMAL_VENTURE, false)
         }
     }
 
@@ -29,156 +123,671 @@ pub mod cli {
             HYDRO_DIAGONAL
         }
 
-        fn run(&mut self, matches: &clap::ArgMatches) -> anyhow::Result<String> { 
-            let input = read_input_from_matches(self, matches)?;
-            
-            let answer = run_hydrotermal_diagonals(input);
+        fn configure<'a>(&self, subcommand: clap::App<'a>) -> clap::App<'a> {
+            add_floor_args(subcommand)
+        }
 
-            Ok(format!("Answer is: {}", answer?))
+        fn prepare(&mut self, input: InputLines, matches: &clap::ArgMatches) -> anyhow::Result<PreparedSolution> { 
+            prepare_overlaps(input, matches, HYDRO_DIAGONAL, true)
         }
     }
 }
 
+use crate::error::SyntaxError;
+use crate::error::UsageError;
+
+pub type PointCoord = i64;
+pub type Point = [PointCoord; 2];
+/// A line of vents given by its two end points.
+pub type Segment = [Point; 2];
+/// Number of vents crossing a single point.
+pub type VentCount = u32;
+/// Number of points crossed by each number of vents.
+pub type OverlapHistogram = std::collections::BTreeMap<VentCount, usize>;
+
+// The most cells a dense floor is allowed to allocate
+const DENSE_FLOOR_MAX_CELLS: usize = 1 << 28;
+
+/// A grid counting the vents at each point of the ocean floor.
+pub trait OceanFloor {
+    /// Marks a vent at `point` and returns how many vents were there before.
+    fn mark(&mut self, point: Point) -> VentCount;
+    /// Returns how many vents cross `point`.
+    fn get(&self, point: Point) -> VentCount;
+    /// Iterates over the points with at least one vent.
+    fn cells(&self) -> Box<dyn Iterator<Item = (Point, VentCount)> + '_>;
+}
+
+/// An ocean floor stored as an array covering the bounding box of the segments.
+pub struct DenseFloor {
+    origin: Point,
+    width: usize,
+    cells: Vec<VentCount>,
+}
 
-const FLOOR_WIDTH: usize = 1000;
-const FLOOR_HEIGHT: usize = 1000;
-type OceanFloor = Box<[u8; FLOOR_WIDTH * FLOOR_HEIGHT]>;
-type PointCoord = u16;
-type Point = [PointCoord; 2];
+// Returns the width and height of the box between the `min` and `max` corners, along with its
+// number of points if it is small enough to be allocated
+fn box_size(min: Point, max: Point) -> (u128, u128, Option<usize>) {
+    let width = (max[0] as i128 - min[0] as i128 + 1) as u128;
+    let height = (max[1] as i128 - min[1] as i128 + 1) as u128;
+    let cells = width.checked_mul(height).filter(|&cells| cells <= DENSE_FLOOR_MAX_CELLS as u128);
 
-fn new_floor() -> OceanFloor {
-    Box::new([0; FLOOR_WIDTH * FLOOR_HEIGHT])
+    (width, height, cells.map(|cells| cells as usize))
 }
 
-fn point_to_pos(x: usize, y: usize) -> usize {
-    y * FLOOR_WIDTH + x
+impl DenseFloor {
+    pub fn new(segments: &[Segment]) -> anyhow::Result<Self> {
+        let (min, max) = bounding_box(segments);
+        let (width, height, cells) = box_size(min, max);
+        let cells = cells.ok_or_else(|| anyhow::anyhow!("The floor of {}x{} points is too large to be dense, use the sparse floor instead", width, height))?;
+
+        Ok(Self { origin: min, width: width as usize, cells: vec![0; cells] })
+    }
+
+    fn point_to_pos(&self, point: Point) -> usize {
+        (point[1] - self.origin[1]) as usize * self.width + (point[0] - self.origin[0]) as usize
+    }
+
+    fn contains(&self, point: Point) -> bool {
+        let height = (self.cells.len() / self.width) as i128;
+        (0..self.width as i128).contains(&(point[0] as i128 - self.origin[0] as i128))
+            && (0..height).contains(&(point[1] as i128 - self.origin[1] as i128))
+    }
 }
 
-// Returns number of overlaps
-fn horizontal_line(floor: &mut OceanFloor, x: PointCoord, mut y1: PointCoord, mut y2: PointCoord) -> usize {
-    if y1 > y2 {
-        std::mem::swap(&mut y1, &mut y2);
+impl OceanFloor for DenseFloor {
+    fn mark(&mut self, point: Point) -> VentCount {
+        let pos = self.point_to_pos(point);
+        let val = &mut self.cells[pos];
+        let previous = *val;
+        *val = previous.saturating_add(1);
+        previous
     }
-    
-    (y1..y2+1).fold(0, |acc, y| {
-        let val = &mut floor[point_to_pos(x as usize, y as usize)];
-        match val {
-            0 => { *val += 1; acc },
-            1 => { *val += 1; acc + 1 },
-            2 => { acc }
-            _ => panic!("Line was marked twice")
+
+    fn get(&self, point: Point) -> VentCount {
+        match self.contains(point) {
+            true => self.cells[self.point_to_pos(point)],
+            false => 0,
         }
-    })
+    }
+
+    fn cells(&self) -> Box<dyn Iterator<Item = (Point, VentCount)> + '_> {
+        Box::new(self.cells.iter().enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(pos, &count)| {
+                let point = [self.origin[0] + (pos % self.width) as PointCoord, self.origin[1] + (pos / self.width) as PointCoord];
+                (point, count)
+            }))
+    }
+}
+
+/// An ocean floor storing only the marked points, for huge or negative coordinates.
+#[derive(Default)]
+pub struct SparseFloor {
+    cells: std::collections::HashMap<Point, VentCount, ahash::RandomState>,
+}
+
+impl OceanFloor for SparseFloor {
+    fn mark(&mut self, point: Point) -> VentCount {
+        let val = self.cells.entry(point).or_insert(0);
+        let previous = *val;
+        *val = previous.saturating_add(1);
+        previous
+    }
+
+    fn get(&self, point: Point) -> VentCount {
+        self.cells.get(&point).copied().unwrap_or(0)
+    }
+
+    fn cells(&self) -> Box<dyn Iterator<Item = (Point, VentCount)> + '_> {
+        Box::new(self.cells.iter().map(|(&point, &count)| (point, count)))
+    }
+}
+
+/// A floor of the kind chosen at runtime.
+pub enum AnyFloor {
+    Dense(DenseFloor),
+    Sparse(SparseFloor),
+}
+
+impl AnyFloor {
+    /// Creates an empty floor able to hold all of the segments.
+    pub fn new(kind: FloorKind, segments: &[Segment]) -> anyhow::Result<Self> {
+        match kind {
+            FloorKind::Dense => Ok(AnyFloor::Dense(DenseFloor::new(segments)?)),
+            FloorKind::Sparse => Ok(AnyFloor::Sparse(SparseFloor::default())),
+            FloorKind::Auto => match DenseFloor::new(segments) {
+                Ok(floor) => Ok(AnyFloor::Dense(floor)),
+                Err(_) => Ok(AnyFloor::Sparse(SparseFloor::default())),
+            },
+        }
+    }
+}
+
+impl OceanFloor for AnyFloor {
+    fn mark(&mut self, point: Point) -> VentCount {
+        match self {
+            AnyFloor::Dense(floor) => floor.mark(point),
+            AnyFloor::Sparse(floor) => floor.mark(point),
+        }
+    }
+
+    fn get(&self, point: Point) -> VentCount {
+        match self {
+            AnyFloor::Dense(floor) => floor.get(point),
+            AnyFloor::Sparse(floor) => floor.get(point),
+        }
+    }
+
+    fn cells(&self) -> Box<dyn Iterator<Item = (Point, VentCount)> + '_> {
+        match self {
+            AnyFloor::Dense(floor) => floor.cells(),
+            AnyFloor::Sparse(floor) => floor.cells(),
+        }
+    }
+}
+
+/// Which [`OceanFloor`] the vents are counted on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloorKind {
+    Dense,
+    Sparse,
+    // Dense if the bounding box of the segments is small enough, sparse otherwise
+    Auto,
 }
 
-// Returns number of overlaps
-fn vertical_line(floor: &mut OceanFloor, y: PointCoord, mut x1: PointCoord, mut x2: PointCoord) -> usize {
-    if x1 > x2 {
-        std::mem::swap(&mut x1, &mut x2);
+impl FloorKind {
+    pub const VARIANTS: [&'static str; 3] = ["auto", "dense", "sparse"];
+}
+
+impl std::str::FromStr for FloorKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dense" => Ok(FloorKind::Dense),
+            "sparse" => Ok(FloorKind::Sparse),
+            "auto" => Ok(FloorKind::Auto),
+            other => Err(anyhow::anyhow!("Unknown floor '{}'", other)),
+        }
     }
+}
 
-    (x1..x2+1).fold(0, |acc, x| {
-        let val = &mut floor[point_to_pos(x as usize, y as usize)];
-        match val {
-            0 => { *val += 1; acc },
-            1 => { *val += 1; acc + 1 },
-            2 => { acc }
-            _ => panic!("Line was marked twice")
+/// How segments that are neither horizontal, vertical nor diagonal are drawn. Both give the same
+/// points for the other segments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rasterization {
+    // Only the points the segment passes through exactly
+    Lattice,
+    // The point closest to the segment on every column or row, whichever it spans more of
+    Bresenham,
+}
+
+impl Rasterization {
+    pub const VARIANTS: [&'static str; 2] = ["lattice", "bresenham"];
+}
+
+impl std::str::FromStr for Rasterization {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lattice" => Ok(Rasterization::Lattice),
+            "bresenham" => Ok(Rasterization::Bresenham),
+            other => Err(anyhow::anyhow!("Unknown rasterization '{}'", other)),
+        }
+    }
+}
+
+/// Formats the floor can be rendered in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderFormat {
+    // The diagram from the puzzle description, '.' for no vents and the count otherwise
+    Ascii,
+    // Grayscale image, brighter where more vents cross
+    Pgm,
+    // Heatmap image
+    Ppm,
+}
+
+impl RenderFormat {
+    pub const VARIANTS: [&'static str; 3] = ["ascii", "pgm", "ppm"];
+}
+
+impl std::str::FromStr for RenderFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascii" => Ok(RenderFormat::Ascii),
+            "pgm" => Ok(RenderFormat::Pgm),
+            "ppm" => Ok(RenderFormat::Ppm),
+            other => Err(anyhow::anyhow!("Unknown render format '{}'", other)),
         }
+    }
+}
+
+/// Renders the points of the floor between the `min` and `max` corners as the diagram from the
+/// puzzle description. Points crossed by more than 9 vents are shown as '#'.
+pub fn render_ascii(floor: &impl OceanFloor, min: Point, max: Point) -> String {
+    (min[1]..=max[1])
+        .map(|y| (min[0]..=max[0])
+            .map(|x| match floor.get([x, y]) {
+                0 => '.',
+                count @ 1..=9 => char::from_digit(count, 10).unwrap(),
+                _ => '#',
+            })
+            .collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Writes the points of the floor between the `min` and `max` corners as a PGM or PPM image,
+/// scaling the counts of vents to the most crossed point.
+pub fn render_image(floor: &impl OceanFloor, min: Point, max: Point, format: RenderFormat, out: &mut impl std::io::Write) -> anyhow::Result<()> {
+    use crate::core::image;
+
+    let (width, height, cells) = box_size(min, max);
+    if cells.is_none() {
+        return Err(UsageError(format!("The image of {}x{} pixels is too large", width, height)).into());
+    }
+    let (width, height) = (width as usize, height as usize);
+
+    let highest = floor.cells().map(|(_, count)| count).max().unwrap_or(1) as f64;
+    let intensities = (min[1]..=max[1])
+        .flat_map(|y| (min[0]..=max[0]).map(move |x| [x, y]))
+        .map(|point| floor.get(point) as f64 / highest);
+
+    match format {
+        RenderFormat::Pgm => image::write_pgm(out, width, height, &intensities.map(|value| (value * 255.0).round() as u8).collect::<Vec<_>>())?,
+        RenderFormat::Ppm => image::write_ppm(out, width, height, &intensities.map(image::heatmap_color).collect::<Vec<_>>())?,
+        RenderFormat::Ascii => writeln!(out, "{}", render_ascii(floor, min, max))?,
+    }
+
+    Ok(())
+}
+
+/// Returns the lowest and the highest corner of the box containing all of the segments.
+pub fn bounding_box(segments: &[Segment]) -> (Point, Point) {
+    if segments.is_empty() {
+        return ([0, 0], [0, 0]);
+    }
+
+    segments.iter().flatten().fold(([PointCoord::MAX; 2], [PointCoord::MIN; 2]), |(min, max), point| {
+        ([min[0].min(point[0]), min[1].min(point[1])], [max[0].max(point[0]), max[1].max(point[1])])
     })
 }
 
-fn diagonal(floor: &mut OceanFloor, p1: Point, p2: Point) -> usize {
-    let mut start = p1[0];
-    let mut end = p2[0];
-    if start > end { std::mem::swap(&mut start, &mut end); }
+fn is_diagonal(segment: &Segment) -> bool {
+    let [p1, p2] = segment;
+    p1[0] != p2[0] && p1[1] != p2[1]
+}
+
+fn greatest_common_divisor(a: i128, b: i128) -> i128 {
+    match b {
+        0 => a,
+        _ => greatest_common_divisor(b, a % b),
+    }
+}
+
+// Returns the points a segment passes through exactly, evenly spaced between its ends. The
+// distances are computed in i128, as they can exceed the range of a coordinate
+fn lattice_points(segment: &Segment) -> impl Iterator<Item = Point> {
+    let [p1, p2] = segment.map(|point| point.map(i128::from));
+    let [dx, dy] = [p2[0] - p1[0], p2[1] - p1[1]];
+    let steps = greatest_common_divisor(dx.abs(), dy.abs());
+    let [inc_x, inc_y] = match steps {
+        0 => [0, 0],
+        _ => [dx / steps, dy / steps],
+    };
+
+    // Every point lies between the ends, so it fits in a coordinate again
+    (0..=steps).map(move |step| [(p1[0] + step * inc_x) as PointCoord, (p1[1] + step * inc_y) as PointCoord])
+}
+
+// Returns the points of a segment drawn with Bresenham's line algorithm
+fn bresenham_points(segment: &Segment) -> impl Iterator<Item = Point> {
+    let [p1, p2] = *segment;
+    let [dx, dy] = [(p2[0] as i128 - p1[0] as i128).abs(), -(p2[1] as i128 - p1[1] as i128).abs()];
+    let [inc_x, inc_y] = [p2[0].cmp(&p1[0]) as PointCoord, p2[1].cmp(&p1[1]) as PointCoord];
+    let mut error = dx + dy;
+    let mut next = Some(p1);
+
+    std::iter::from_fn(move || {
+        let point = next?;
+        next = (point != p2).then(|| {
+            let doubled = 2 * error;
+            let mut moved = point;
+            if doubled >= dy {
+                error += dy;
+                moved[0] += inc_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                moved[1] += inc_y;
+            }
+            moved
+        });
+
+        Some(point)
+    })
+}
 
-    let inc_x: isize = if p1[0] > p2[0] { -1 } else { 1 };
-    let inc_y: isize = if p1[1] > p2[1] { -1 } else { 1 };
+// Marks every point of a segment, returning the number of points that became overlaps
+fn draw_segment(floor: &mut impl OceanFloor, segment: &Segment, raster: Rasterization) -> usize {
+    let mark = |acc, point| match floor.mark(point) {
+        1 => acc + 1,
+        _ => acc,
+    };
 
-    let mut acc = 0;
-    let mut x = p1[0] as isize;
-    let mut y = p1[1] as isize;
+    match raster {
+        Rasterization::Lattice => lattice_points(segment).fold(0, mark),
+        Rasterization::Bresenham => bresenham_points(segment).fold(0, mark),
+    }
+}
 
-    let x2 = p2[0] as isize;
-    while x != x2 {
-        let val = &mut floor[point_to_pos(x as usize, y as usize)];
+// The line a segment lies on, as the coefficients of 'a*x + b*y = c'. Coordinates are widened
+// so that 'c' can't overflow for diagonal lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct SegmentLine {
+    a: i128,
+    b: i128,
+    c: i128,
+}
 
-        acc += match val {
-            0 => { *val += 1; 0 },
-            1 => { *val += 1; 1 },
-            2 => { 0 }
-            _ => panic!("Line was marked twice")
+impl SegmentLine {
+    // Coefficients of vertical, horizontal, ascending and descending diagonal lines
+    const DIRECTIONS: [(i128, i128); 4] = [(1, 0), (0, 1), (1, -1), (1, 1)];
+
+    // Returns the line of a horizontal, vertical or 45 degree segment and the range of positions
+    // it covers on the line, or None for segments at any other angle
+    fn of(segment: &Segment) -> Option<(Self, [i128; 2])> {
+        let [[x1, y1], [x2, y2]] = segment.map(|point| point.map(i128::from));
+        let (a, b) = match (x2 - x1, y2 - y1) {
+            (0, _) => (1, 0),
+            (_, 0) => (0, 1),
+            (dx, dy) if dx == dy => (1, -1),
+            (dx, dy) if dx == -dy => (1, 1),
+            _ => return None,
         };
-        
-        x += inc_x;
-        y += inc_y;
+
+        let line = Self { a, b, c: a * x1 + b * y1 };
+        let [start, end] = [line.position([x1, y1]), line.position([x2, y2])];
+
+        Some((line, [start.min(end), start.max(end)]))
     }
 
-    acc
+    // Position of a point of the line, which is its y for vertical lines and its x otherwise
+    fn position(&self, point: [i128; 2]) -> i128 {
+        match self.b {
+            0 => point[1],
+            _ => point[0],
+        }
+    }
+
+    // Returns the point where two lines of different directions cross, if it has integer coordinates
+    fn crossing(&self, other: &Self) -> Option<[i128; 2]> {
+        let det = self.a * other.b - other.a * self.b;
+        if det == 0 {
+            return None;
+        }
+
+        let x = self.c * other.b - other.c * self.b;
+        let y = self.a * other.c - other.a * self.c;
+        (x % det == 0 && y % det == 0).then(|| [x / det, y / det])
+    }
 }
 
-fn read_line_to_pair_points(line: &str) -> [Point; 2] {
-    line
-        .split(" -> ").collect::<arrayvec::ArrayVec<_, 2>>().iter()
-        .map(|point| {
-            point.split(',')
-                .map(|val| val.parse::<PointCoord>().unwrap())
-                .collect::<arrayvec::ArrayVec<_, 2>>().into_inner()
-        })
-        .collect::<Result<arrayvec::ArrayVec<_, 2>, _>>().unwrap().into_inner().unwrap()
+// Returns the sorted ranges of positions covered by at least `min_count` of the given ranges.
+// Ranges are inclusive.
+fn covered_ranges(ranges: &[[i128; 2]], min_count: usize) -> Vec<[i128; 2]> {
+    let mut events: Vec<(i128, isize)> = ranges.iter().flat_map(|&[start, end]| [(start, 1), (end + 1, -1)]).collect();
+    events.sort_unstable();
+
+    let mut covered = Vec::new();
+    let mut count = 0;
+    let mut start = 0;
+    for (position, change) in events {
+        let previous = count;
+        count += change;
+
+        if previous < min_count as isize && count >= min_count as isize {
+            start = position;
+        } else if previous >= min_count as isize && count < min_count as isize {
+            covered.push([start, position - 1]);
+        }
+    }
+
+    covered
 }
 
-pub fn run_hydrotermal_venture<S: AsRef<str>>(input: impl IntoIterator<Item = std::io::Result<S>>) -> anyhow::Result<usize> {
-    let mut floor = new_floor();
+fn is_covered(ranges: &[[i128; 2]], position: i128) -> bool {
+    let after = ranges.partition_point(|[start, _]| *start <= position);
+    after > 0 && ranges[after - 1][1] >= position
+}
 
-    let overlaps: std::io::Result<usize> = input.into_iter().map(|result| {
-        let line = result?;
+/// Counts the points where at least two segments overlap without drawing them on a floor.
+/// Segments on the same line are merged as ranges, and only the points where different lines
+/// cross are computed, so memory grows with the number of segments and their crossings rather
+/// than with the area they span. Fails on segments that aren't horizontal, vertical or diagonal.
+pub fn count_overlaps_by_intersection(segments: &[Segment], diagonals: bool) -> anyhow::Result<usize> {
+    let mut lines: std::collections::HashMap<SegmentLine, Vec<[i128; 2]>, ahash::RandomState> = Default::default();
+    for segment in segments.iter().filter(|segment| diagonals || !is_diagonal(segment)) {
+        let (line, range) = SegmentLine::of(segment)
+            .ok_or_else(|| anyhow::anyhow!("Segment {:?} -> {:?} is neither horizontal, vertical nor diagonal", segment[0], segment[1]))?;
+
+        lines.entry(line).or_default().push(range);
+    }
 
-        if line.as_ref().is_empty() { return Ok(0) };
+    // Ranges of each line covered by any of its segments, and by at least two of them
+    let lines: Vec<_> = lines.into_iter()
+        .map(|(line, ranges)| (line, covered_ranges(&ranges, 1), covered_ranges(&ranges, 2)))
+        .collect();
+    let overlaps: std::collections::HashMap<SegmentLine, &[[i128; 2]], ahash::RandomState> = lines.iter()
+        .map(|(line, _, overlaps)| (*line, overlaps.as_slice()))
+        .collect();
+
+    let collinear: i128 = overlaps.values().copied().flatten().map(|[start, end]| end - start + 1).sum();
+
+    let mut crossings = std::collections::HashSet::<[i128; 2], ahash::RandomState>::default();
+    for (i, (line, covered, _)) in lines.iter().enumerate() {
+        for (other, other_covered, _) in &lines[i + 1..] {
+            if let Some(point) = line.crossing(other) {
+                if is_covered(covered, line.position(point)) && is_covered(other_covered, other.position(point)) {
+                    crossings.insert(point);
+                }
+            }
+        }
+    }
 
-        let [p1, p2] = read_line_to_pair_points(line.as_ref());
-        
-        let overlaps = {
-            if p1[0] == p2[0] { horizontal_line(&mut floor, p1[0], p1[1], p2[1]) }
-            else if p1[1] == p2[1] { vertical_line(&mut floor, p1[1], p1[0], p2[0]) }
-            else { 0 }
-        };
+    // Every crossing is an overlap, but it was already counted once for each of its lines where
+    // it is also covered by collinear segments
+    let counted_crossings: i128 = crossings.iter()
+        .map(|&[x, y]| SegmentLine::DIRECTIONS.iter()
+            .map(|&(a, b)| SegmentLine { a, b, c: a * x + b * y })
+            .filter(|line| overlaps.get(line).is_some_and(|ranges| is_covered(ranges, line.position([x, y]))))
+            .count() as i128)
+        .sum();
 
-        Ok(overlaps)
-    }).try_fold(0, |acc, x: std::io::Result<usize>| Ok(acc+x?));
+    Ok(usize::try_from(collinear + crossings.len() as i128 - counted_crossings)?)
+}
 
-    Ok(overlaps?)
+// Reads the tokens of a line, keeping track of the column for errors
+struct SegmentTokens<'a> {
+    line: &'a str,
+    line_number: usize,
+    pos: usize,
 }
 
-pub fn run_hydrotermal_diagonals<S: AsRef<str>>(input: impl IntoIterator<Item = std::io::Result<S>>) -> anyhow::Result<usize> {
-    let mut floor = new_floor();
+impl<'a> SegmentTokens<'a> {
+    fn skip_whitespace(&mut self) {
+        self.pos = self.line.len() - self.line[self.pos..].trim_start().len();
+    }
+
+    fn error(&self, expected: &'static str) -> SyntaxError {
+        let token = self.line[self.pos..].split(char::is_whitespace).next().unwrap_or_default();
+
+        SyntaxError {
+            line: self.line_number,
+            column: self.line[..self.pos].chars().count() + 1,
+            token: token.to_string(),
+            expected,
+        }
+    }
+
+    fn expect(&mut self, symbol: &str, expected: &'static str) -> Result<(), SyntaxError> {
+        self.skip_whitespace();
+        match self.line[self.pos..].starts_with(symbol) {
+            true => {
+                self.pos += symbol.len();
+                Ok(())
+            },
+            false => Err(self.error(expected)),
+        }
+    }
+
+    fn coordinate(&mut self) -> Result<PointCoord, SyntaxError> {
+        self.skip_whitespace();
+        let rest = &self.line[self.pos..];
+        let sign = usize::from(rest.starts_with('-'));
+        let length = sign + rest[sign..].find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len() - sign);
+
+        let coordinate = rest[..length].parse().map_err(|_| self.error("an integer coordinate"))?;
+        self.pos += length;
+
+        Ok(coordinate)
+    }
+
+    fn point(&mut self) -> Result<Point, SyntaxError> {
+        let x = self.coordinate()?;
+        self.expect(",", "',' between coordinates")?;
+        let y = self.coordinate()?;
 
-    let overlaps: std::io::Result<usize> = input.into_iter().map(|result| {
+        Ok([x, y])
+    }
+
+    fn end(&mut self) -> Result<(), SyntaxError> {
+        self.skip_whitespace();
+        match self.pos == self.line.len() {
+            true => Ok(()),
+            false => Err(self.error("the end of the line")),
+        }
+    }
+}
+
+// Parses a line like '0,9 -> 5,9', allowing any whitespace around the numbers and the arrow
+fn read_line_to_pair_points(line: &str, line_number: usize) -> Result<Segment, SyntaxError> {
+    let mut tokens = SegmentTokens { line, line_number, pos: 0 };
+
+    let start = tokens.point()?;
+    tokens.expect("->", "'->' between points")?;
+    let end = tokens.point()?;
+    tokens.end()?;
+
+    Ok([start, end])
+}
+
+/// Parses the lines of vents, skipping blank lines.
+pub fn read_segments<S: AsRef<str>>(input: impl IntoIterator<Item = std::io::Result<S>>) -> anyhow::Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+
+    for (index, result) in input.into_iter().enumerate() {
         let line = result?;
+        if line.as_ref().trim().is_empty() { continue };
 
-        if line.as_ref().is_empty() { return Ok(0) };
+        segments.push(read_line_to_pair_points(line.as_ref(), index + 1)?);
+    }
 
-        let [p1, p2]: [Point; 2] = read_line_to_pair_points(line.as_ref());
-        
-        let overlaps = {
-            if p1[0] == p2[0] { horizontal_line(&mut floor, p1[0], p1[1], p2[1]) }
-            else if p1[1] == p2[1] { vertical_line(&mut floor, p1[1], p1[0], p2[0]) }
-            else { diagonal(&mut floor, p1, p2) }
-        };
+    Ok(segments)
+}
 
-        Ok(overlaps)
-    }).try_fold(0, |acc, x: std::io::Result<usize>| Ok(acc+x?));
+/// Counts the points where at least two segments overlap on the given floor. Segments that
+/// aren't horizontal or vertical are skipped unless `diagonals` is set.
+pub fn count_overlaps(floor: &mut impl OceanFloor, segments: &[Segment], diagonals: bool, raster: Rasterization) -> usize {
+    segments.iter()
+        .filter(|segment| diagonals || !is_diagonal(segment))
+        .map(|segment| draw_segment(floor, segment, raster))
+        .sum()
+}
+
+/// Same as [`count_overlaps`], on a floor of the given kind.
+pub fn count_overlaps_on(kind: FloorKind, segments: &[Segment], diagonals: bool, raster: Rasterization) -> anyhow::Result<usize> {
+    Ok(count_overlaps(&mut AnyFloor::new(kind, segments)?, segments, diagonals, raster))
+}
+
+/// Returns how many points are crossed by each number of vents on a floor with segments drawn.
+pub fn overlap_histogram(floor: &impl OceanFloor) -> OverlapHistogram {
+    floor.cells().fold(OverlapHistogram::new(), |mut histogram, (_, count)| {
+        *histogram.entry(count).or_insert(0) += 1;
+        histogram
+    })
+}
+
+/// Draws the segments on a floor of the given kind and returns its [`overlap_histogram`].
+pub fn overlap_histogram_on(kind: FloorKind, segments: &[Segment], diagonals: bool, raster: Rasterization) -> anyhow::Result<OverlapHistogram> {
+    let mut floor = AnyFloor::new(kind, segments)?;
+    count_overlaps(&mut floor, segments, diagonals, raster);
 
-    Ok(overlaps?)
+    Ok(overlap_histogram(&floor))
+}
+
+/// Draws the segments on a floor of the given kind and renders it cropped to the drawn segments.
+/// Returns the width and height of the rendered map.
+pub fn render_on(kind: FloorKind, segments: &[Segment], diagonals: bool, raster: Rasterization, format: RenderFormat, out: &mut impl std::io::Write) -> anyhow::Result<(usize, usize)> {
+    let drawn: Vec<Segment> = segments.iter().filter(|segment| diagonals || !is_diagonal(segment)).copied().collect();
+    let mut floor = AnyFloor::new(kind, &drawn)?;
+    count_overlaps(&mut floor, &drawn, diagonals, raster);
+
+    let (min, max) = bounding_box(&drawn);
+    render_image(&floor, min, max, format, out)?;
+
+    // The image was only rendered if its size fits
+    let (width, height, _) = box_size(min, max);
+    Ok((width as usize, height as usize))
+}
+
+/// Returns the number of points crossed by at least `threshold` vents, which is never less than one.
+pub fn count_at_least(histogram: &OverlapHistogram, threshold: VentCount) -> usize {
+    histogram.range(threshold.max(1)..).map(|(_, points)| points).sum()
+}
+
+/// Counts the points where at least two horizontal or vertical lines of vents overlap.
+pub fn run_hydrotermal_venture<S: AsRef<str>>(input: impl IntoIterator<Item = std::io::Result<S>>) -> anyhow::Result<usize> {
+    let segments = read_segments(input)?;
+
+    count_overlaps_on(FloorKind::Auto, &segments, false, Rasterization::Lattice)
+}
+
+/// Same as [`run_hydrotermal_venture`], but also takes diagonal lines into account.
+pub fn run_hydrotermal_diagonals<S: AsRef<str>>(input: impl IntoIterator<Item = std::io::Result<S>>) -> anyhow::Result<usize> {
+    let segments = read_segments(input)?;
+
+    count_overlaps_on(FloorKind::Auto, &segments, true, Rasterization::Lattice)
 }
 
 
 
 #[cfg(test)]
+#[allow(clippy::redundant_closure)]
 mod test {
+    use crate::day_5::bresenham_points;
+    use crate::day_5::count_overlaps;
+    use crate::day_5::count_at_least;
+    use crate::day_5::count_overlaps_by_intersection;
+    use crate::day_5::count_overlaps_on;
+    use crate::day_5::lattice_points;
+    use crate::day_5::overlap_histogram_on;
+    use crate::day_5::read_segments;
+    use crate::day_5::render_on;
     use crate::day_5::run_hydrotermal_diagonals;
     use crate::day_5::run_hydrotermal_venture;
+    use crate::day_5::DenseFloor;
+    use crate::day_5::FloorKind;
+    use crate::day_5::PointCoord;
+    use crate::day_5::Rasterization;
+    use crate::day_5::RenderFormat;
+    use crate::day_5::SparseFloor;
+    use crate::error::SyntaxError;
 
     const EXAMPLE: &str = "0,9 -> 5,9\n\
         8,0 -> 0,8\n\
@@ -204,4 +813,164 @@ mod test {
 
         assert_eq!(12, answer.unwrap());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn it_counts_same_overlaps_on_dense_and_sparse_floor() {
+        let segments = read_segments(EXAMPLE.split('\n').map(Ok)).unwrap();
+
+        for diagonals in [false, true] {
+            let dense = count_overlaps(&mut DenseFloor::new(&segments).unwrap(), &segments, diagonals, Rasterization::Lattice);
+            let sparse = count_overlaps(&mut SparseFloor::default(), &segments, diagonals, Rasterization::Lattice);
+
+            assert_eq!(dense, sparse);
+        }
+    }
+
+    #[test]
+    fn it_handles_large_and_negative_coordinates() {
+        const FAR_EXAMPLE: &str = "-5,2 -> 5,2\n\
+            0,-3 -> 0,7\n\
+            -2,-2 -> 4,4\n\
+            1000000,1000000 -> 1000000,1000005\n\
+            1000000,1000003 -> 999998,1000003";
+
+        let segments = read_segments(FAR_EXAMPLE.split('\n').map(Ok)).unwrap();
+
+        assert!(count_overlaps_on(FloorKind::Dense, &segments, true, Rasterization::Lattice).is_err());
+        assert_eq!(count_overlaps_on(FloorKind::Sparse, &segments, false, Rasterization::Lattice).unwrap(), 2);
+        assert_eq!(count_overlaps_on(FloorKind::Auto, &segments, true, Rasterization::Lattice).unwrap(), 4);
+    }
+
+    #[test]
+    fn it_falls_back_to_sparse_floor_when_extent_overflows() {
+        const OPPOSITE_EXAMPLE: &str = "-5000000000000000000,0 -> -5000000000000000000,3\n\
+            5000000000000000000,1 -> 5000000000000000000,1";
+
+        let segments = read_segments(OPPOSITE_EXAMPLE.split('\n').map(Ok)).unwrap();
+
+        assert!(count_overlaps_on(FloorKind::Dense, &segments, false, Rasterization::Lattice).is_err());
+        assert_eq!(count_overlaps_on(FloorKind::Auto, &segments, false, Rasterization::Lattice).unwrap(), 0);
+        assert_eq!(count_overlaps_on(FloorKind::Sparse, &segments, false, Rasterization::Lattice).unwrap(), 0);
+        assert!(render_on(FloorKind::Sparse, &segments, false, Rasterization::Lattice, RenderFormat::Ascii, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn it_counts_vents_crossing_each_point() {
+        let segments = read_segments(EXAMPLE.split('\n').map(Ok)).unwrap();
+        let histogram = overlap_histogram_on(FloorKind::Dense, &segments, true, Rasterization::Lattice).unwrap();
+
+        assert_eq!(histogram.get(&3), Some(&2));
+        assert_eq!(histogram.keys().max(), Some(&3));
+        assert_eq!(count_at_least(&histogram, 2), 12);
+        assert_eq!(count_at_least(&histogram, 3), 2);
+        assert_eq!(overlap_histogram_on(FloorKind::Sparse, &segments, true, Rasterization::Lattice).unwrap(), histogram);
+    }
+
+    #[test]
+    fn it_renders_example_diagram() {
+        let segments = read_segments(EXAMPLE.split('\n').map(Ok)).unwrap();
+        let mut diagram = Vec::new();
+
+        assert_eq!(render_on(FloorKind::Sparse, &segments, true, Rasterization::Lattice, RenderFormat::Ascii, &mut diagram).unwrap(), (10, 10));
+        assert_eq!(String::from_utf8(diagram).unwrap(), "1.1....11.\n\
+            .111...2..\n\
+            ..2.1.111.\n\
+            ...1.2.2..\n\
+            .112313211\n\
+            ...1.2....\n\
+            ..1...1...\n\
+            .1.....1..\n\
+            1.......1.\n\
+            222111....\n");
+
+        let mut image = Vec::new();
+        render_on(FloorKind::Dense, &segments, false, Rasterization::Lattice, RenderFormat::Pgm, &mut image).unwrap();
+
+        assert!(image.starts_with(b"P5\n10 10\n255\n"));
+        assert_eq!(image.len(), 13 + 100);
+    }
+
+    #[test]
+    fn it_counts_same_overlaps_from_intersections_as_on_grid() {
+        // Segments in every direction on a small area, so that many of them overlap and cross
+        let mut state: u64 = 0x2545f4914f6cdd1d;
+        let mut next = |bound: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound) as i64
+        };
+
+        let mut segments = read_segments(EXAMPLE.split('\n').map(Ok)).unwrap();
+        for _ in 0..300 {
+            let start = [next(40), next(40)];
+            let length = next(15);
+            let [dx, dy] = [[1, 0], [0, 1], [1, 1], [1, -1], [-1, 0], [-1, -1]][next(6) as usize];
+            segments.push([start, [start[0] + dx * length, start[1] + dy * length]]);
+        }
+
+        for diagonals in [false, true] {
+            assert_eq!(count_overlaps_by_intersection(&segments, diagonals).unwrap(), count_overlaps_on(FloorKind::Sparse, &segments, diagonals, Rasterization::Lattice).unwrap());
+        }
+    }
+
+    #[test]
+    fn it_counts_overlaps_of_huge_segments_from_intersections() {
+        const HUGE_EXAMPLE: &str = "0,0 -> 1000000000000,1000000000000\n\
+            1000000000000,0 -> 0,1000000000000\n\
+            500000000000,0 -> 500000000000,1000000000000\n\
+            0,500000000000 -> 400000000000,500000000000\n\
+            -7,999999999999 -> 999999999999,999999999999\n\
+            10,10 -> 2000000000,2000000000";
+
+        let segments = read_segments(HUGE_EXAMPLE.split('\n').map(Ok)).unwrap();
+
+        assert_eq!(count_overlaps_by_intersection(&segments, false).unwrap(), 1);
+        // The two collinear diagonals, plus four crossings
+        assert_eq!(count_overlaps_by_intersection(&segments, true).unwrap(), 1999999991 + 4);
+    }
+
+    #[test]
+    fn it_draws_segments_at_any_angle() {
+        let segments = read_segments("0,0 -> 4,2\n2,0 -> 2,3\n0,1 -> 4,1".split('\n').map(Ok)).unwrap();
+
+        // Through (0,0), (2,1) and (4,2) only, or also through (1,1) and (3,2)
+        assert_eq!(count_overlaps_on(FloorKind::Dense, &segments, true, Rasterization::Lattice).unwrap(), 1);
+        assert_eq!(count_overlaps_on(FloorKind::Dense, &segments, true, Rasterization::Bresenham).unwrap(), 2);
+        assert_eq!(count_overlaps_on(FloorKind::Dense, &segments, false, Rasterization::Bresenham).unwrap(), 1);
+        assert!(count_overlaps_by_intersection(&segments, true).is_err());
+    }
+
+    #[test]
+    fn it_draws_segments_between_extreme_coordinates() {
+        let widest = [[PointCoord::MIN, 0], [PointCoord::MAX, 0]];
+        let reversed = [[PointCoord::MAX, 0], [PointCoord::MIN, 0]];
+
+        assert_eq!(lattice_points(&widest).take(2).collect::<Vec<_>>(), vec![[PointCoord::MIN, 0], [PointCoord::MIN + 1, 0]]);
+        assert_eq!(bresenham_points(&widest).take(2).collect::<Vec<_>>(), vec![[PointCoord::MIN, 0], [PointCoord::MIN + 1, 0]]);
+        assert_eq!(lattice_points(&reversed).take(2).collect::<Vec<_>>(), vec![[PointCoord::MAX, 0], [PointCoord::MAX - 1, 0]]);
+        assert_eq!(bresenham_points(&reversed).take(2).collect::<Vec<_>>(), vec![[PointCoord::MAX, 0], [PointCoord::MAX - 1, 0]]);
+
+        // The first segment only passes through its ends, the second one starts at its upper end
+        let segments = read_segments("-9223372036854775808,0 -> 9223372036854775807,1\n9223372036854775807,1 -> 9223372036854775807,5".split('\n').map(Ok)).unwrap();
+
+        assert_eq!(count_overlaps_on(FloorKind::Auto, &segments, true, Rasterization::Lattice).unwrap(), 1);
+    }
+
+    #[test]
+    fn it_reads_segments_with_loose_syntax() {
+        let segments = read_segments(["  0,9->5,9\r", "", "8 , 0  ->\t0,-8\r", "\r"].map(Ok)).unwrap();
+
+        assert_eq!(segments, vec![[[0, 9], [5, 9]], [[8, 0], [0, -8]]]);
+    }
+
+    #[test]
+    fn it_reports_where_segments_are_malformed() {
+        let error_of = |line: &str| read_segments(["0,9 -> 5,9", line].map(Ok)).unwrap_err().downcast::<SyntaxError>().unwrap();
+
+        assert_eq!(error_of("1,2 -> 3,4 -> 5,6"), SyntaxError { line: 2, column: 12, token: "->".to_string(), expected: "the end of the line" });
+        assert_eq!(error_of("1,2x -> 3,4"), SyntaxError { line: 2, column: 3, token: "2x".to_string(), expected: "an integer coordinate" });
+        assert_eq!(error_of("1,2 => 3,4"), SyntaxError { line: 2, column: 5, token: "=>".to_string(), expected: "'->' between points" });
+        assert_eq!(error_of("1,2 -> 3").token, "");
+    }
+}
@@ -1,11 +1,83 @@
 pub mod cli {
-    use crate::day_6::run_unlimited_lanternfish;
-use crate::day_6::run_lanternfish;
+    use crate::command_line::expect_submatches;
+    use crate::command_line::parse_arg;
+    use crate::command_line::parse_optional_arg;
     use crate::command_line::ChallengeSolutionArgs;
-    use crate::command_line::read_input_from_matches;
+    use crate::command_line::PreparedSolution;
+    use crate::core::input::InputLines;
+    use crate::day_6::project;
+    use crate::day_6::project_modulo;
+    use crate::day_6::simulate;
+    use crate::day_6::simulate_series;
+    use crate::error::ParseError;
+    use crate::error::UsageError;
+    use crate::output::Answer;
+    use anyhow::Context;
+
+    fn read_population(mut input: InputLines) -> anyhow::Result<Vec<usize>> {
+        let line = input
+            .next().ok_or_else(|| ParseError("Input is empty".to_string()))??;
+
+        line.split(',')
+            .map(|word| word.trim().parse::<usize>().with_context(|| format!("Can't parse '{}' as a lanternfish timer", word)))
+            .collect()
+    }
+
+    fn add_simulation_args<'a>(subcommand: clap::App<'a>, default_days: &'a str) -> clap::App<'a> {
+        subcommand
+            .arg(clap::Arg::new("days").long("days").takes_value(true).default_value(default_days)
+                .help("Number of days to simulate"))
+            .arg(clap::Arg::new("lifecycle").long("lifecycle").takes_value(true).default_value(LIFECYCLE_DEFAULT)
+                .help("Number of timer values a lanternfish can have, newborn ones start at the highest"))
+            .arg(clap::Arg::new("reset").long("reset").takes_value(true).default_value(RESET_DEFAULT)
+                .help("Timer of a lanternfish after it created a new one"))
+            .arg(clap::Arg::new("series").long("series")
+                .help("Outputs the population at the start and after every day instead of only the last one"))
+            .arg(clap::Arg::new("solver").long("solver").takes_value(true).possible_values(["step", "matrix"]).default_value("step")
+                .help("Simulates the days one by one or projects the population with matrix exponentiation in O(log days)"))
+            .arg(clap::Arg::new("modulo").long("modulo").takes_value(true).value_name("M")
+                .help("Outputs the population modulo M, computed with the matrix solver"))
+    }
+
+    fn prepare_simulation(input: InputLines, matches: &clap::ArgMatches, subcommand: &'static str) -> anyhow::Result<PreparedSolution> {
+        let population = read_population(input)?;
+        let days: u64 = parse_arg(matches, subcommand, "days")?;
+        let lifecycle: usize = parse_arg(matches, subcommand, "lifecycle")?;
+        let reset: usize = parse_arg(matches, subcommand, "reset")?;
+        let modulo: Option<u64> = parse_optional_arg(matches, subcommand, "modulo")?;
+
+        let submatches = expect_submatches(matches, subcommand);
+        let matrix = modulo.is_some() || submatches.value_of("solver") == Some("matrix");
+
+        if matrix {
+            if submatches.is_present("series") {
+                return Err(UsageError("The population series can only be computed with the step solver".to_string()).into());
+            }
+
+            return match modulo {
+                Some(modulo) => Ok(Box::new(move || Ok(project_modulo(&population, days, lifecycle, reset, modulo)?.into()))),
+                None => Ok(Box::new(move || Ok(Answer::BigInteger(project(&population, days, lifecycle, reset)?)))),
+            };
+        }
+
+        let days = usize::try_from(days).map_err(|_| UsageError(format!("{} days are too many to simulate one by one", days)))?;
+
+        if submatches.is_present("series") {
+            Ok(Box::new(move || {
+                let series = simulate_series(&population, days, lifecycle, reset)?;
+
+                Ok(Answer::Series(series.into_iter().map(i128::from).collect()))
+            }))
+        } else {
+            Ok(Box::new(move || Ok(simulate(&population, days, lifecycle, reset)?.into())))
+        }
+    }
 
     const LANTERNFISH: &str = "6_1";
     const UNLIMITED_LANTERNFISH: &str = "6_2";
+    // Same as LANTERNFISH_DAY_LIFECYCLE and LANTERNFISH_RESET_TIMER
+    const LIFECYCLE_DEFAULT: &str = "9";
+    const RESET_DEFAULT: &str = "6";
     pub struct LanternFish { }
 
     impl ChallengeSolutionArgs for LanternFish {
@@ -13,15 +85,12 @@ use crate::day_6::run_lanternfish;
             LANTERNFISH
         }
 
-        fn run(&mut self, matches: &clap::ArgMatches) -> anyhow::Result<String> { 
-            let input: Vec<usize> = read_input_from_matches(self, matches)?
-                .into_iter().next().ok_or(anyhow::anyhow!("Input is empty"))??
-                .split(',').map(|word| word.trim_end().parse::<usize>().unwrap()).collect();
-
-            
-            let answer = run_lanternfish(input);
+        fn configure<'a>(&self, subcommand: clap::App<'a>) -> clap::App<'a> {
+            add_simulation_args(subcommand, "80")
+        }
 
-            Ok(format!("Answer is: {}", answer))
+        fn prepare(&mut self, input: InputLines, matches: &clap::ArgMatches) -> anyhow::Result<PreparedSolution> { 
+            prepare_simulation(input, matches, LANTERNFISH)
         }
     }
 
@@ -32,72 +101,245 @@ use crate::day_6::run_lanternfish;
             UNLIMITED_LANTERNFISH
         }
 
-        fn run(&mut self, matches: &clap::ArgMatches) -> anyhow::Result<String> { 
-            let input: Vec<usize> = read_input_from_matches(self, matches)?
-                .into_iter().next().ok_or(anyhow::anyhow!("Input is empty"))??
-                .split(',').map(|word| word.trim_end().parse::<usize>().unwrap()).collect();
-
-            
-            let answer = run_unlimited_lanternfish(input);
+        fn configure<'a>(&self, subcommand: clap::App<'a>) -> clap::App<'a> {
+            add_simulation_args(subcommand, "256")
+        }
 
-            Ok(format!("Answer is: {}", answer))
+        fn prepare(&mut self, input: InputLines, matches: &clap::ArgMatches) -> anyhow::Result<PreparedSolution> { 
+            prepare_simulation(input, matches, UNLIMITED_LANTERNFISH)
         }
     }
 }
-type FishInt = u64;
-const LANTERNFISH_DAY_LIFECYCLE: usize = 9;
-const LANTERNFISH_RESET_TIMER: usize = 6;
 
-pub fn run_lanternfish(input: Vec<usize>) -> FishInt {
-    let mut day_schedule = input.into_iter().fold([0 as FishInt; LANTERNFISH_DAY_LIFECYCLE], |mut acc, fish| {
-        acc[fish] += 1;
-        acc
-    });
+use crate::error::ParseError;
+use crate::error::UsageError;
 
-    for _ in 0..80 {
-        let birthing_lanternfishes = day_schedule[0];
-        day_schedule.copy_within(1..LANTERNFISH_DAY_LIFECYCLE, 0);
-        day_schedule[LANTERNFISH_DAY_LIFECYCLE-1] = birthing_lanternfishes;
-        day_schedule[LANTERNFISH_RESET_TIMER] += birthing_lanternfishes;
+/// Number of lanternfish with a given timer.
+pub type FishInt = u64;
+/// Number of timer values a lanternfish can have. Newborn ones start with the highest.
+pub const LANTERNFISH_DAY_LIFECYCLE: usize = 9;
+/// Timer of a lanternfish right after it created a new one.
+pub const LANTERNFISH_RESET_TIMER: usize = 6;
+
+// Counts the lanternfish with each of the timers, checking the parameters of the simulation
+fn initial_schedule(population: &[usize], lifecycle: usize, reset: usize) -> anyhow::Result<Vec<FishInt>> {
+    if lifecycle == 0 || reset >= lifecycle {
+        return Err(UsageError(format!("Reset timer {} must be lower than the lifecycle {}", reset, lifecycle)).into());
     }
 
+    let mut day_schedule = vec![0 as FishInt; lifecycle];
+    for &fish in population {
+        let count = day_schedule.get_mut(fish)
+            .ok_or_else(|| ParseError(format!("Timer {} doesn't fit in the lifecycle of {} days", fish, lifecycle)))?;
+        *count += 1;
+    }
 
-    day_schedule.iter().sum::<FishInt>() 
+    Ok(day_schedule)
 }
 
-pub fn run_unlimited_lanternfish(input: Vec<usize>) -> FishInt {
-    let mut day_schedule = input.into_iter().fold([0 as FishInt; LANTERNFISH_DAY_LIFECYCLE], |mut acc, fish| {
-        acc[fish] += 1;
-        acc
-    });
+// Calls `on_day` with the total population at the start and after each of the days
+fn simulate_days(population: &[usize], days: usize, lifecycle: usize, reset: usize, mut on_day: impl FnMut(FishInt)) -> anyhow::Result<()> {
+    let mut day_schedule = initial_schedule(population, lifecycle, reset)?;
+
+    let mut total: FishInt = day_schedule.iter().sum();
+    on_day(total);
 
-    for _ in 0..256 {
+    for day in 0..days {
         let birthing_lanternfishes = day_schedule[0];
-        day_schedule.copy_within(1..LANTERNFISH_DAY_LIFECYCLE, 0);
-        day_schedule[LANTERNFISH_DAY_LIFECYCLE-1] = birthing_lanternfishes;
-        day_schedule[LANTERNFISH_RESET_TIMER] += birthing_lanternfishes;
+        day_schedule.copy_within(1..lifecycle, 0);
+        day_schedule[lifecycle-1] = birthing_lanternfishes;
+        day_schedule[reset] = day_schedule[reset].checked_add(birthing_lanternfishes)
+            .ok_or_else(|| anyhow::anyhow!("Population overflowed on day {}", day + 1))?;
+
+        total = total.checked_add(birthing_lanternfishes)
+            .ok_or_else(|| anyhow::anyhow!("Population overflowed on day {}", day + 1))?;
+        on_day(total);
+    }
+
+    Ok(())
+}
+
+/// Returns the number of lanternfish after `days`, where each fish creates a new one every
+/// `reset + 1` days and newborn ones take `lifecycle` days to create their first.
+pub fn simulate(population: &[usize], days: usize, lifecycle: usize, reset: usize) -> anyhow::Result<FishInt> {
+    let mut answer = 0;
+    simulate_days(population, days, lifecycle, reset, |total| answer = total)?;
+
+    Ok(answer)
+}
+
+/// Same as [`simulate`], but returns the population at the start and after each day.
+pub fn simulate_series(population: &[usize], days: usize, lifecycle: usize, reset: usize) -> anyhow::Result<Vec<FishInt>> {
+    let mut series = Vec::with_capacity(days + 1);
+    simulate_days(population, days, lifecycle, reset, |total| series.push(total))?;
+
+    Ok(series)
+}
+
+// Numbers the projection is computed with
+trait Arithmetic {
+    type Value: Clone;
+
+    fn value(&self, count: FishInt) -> Self::Value;
+    fn add(&self, a: &Self::Value, b: &Self::Value) -> Self::Value;
+    fn mul(&self, a: &Self::Value, b: &Self::Value) -> Self::Value;
+}
+
+struct Exact;
+
+impl Arithmetic for Exact {
+    type Value = num_bigint::BigUint;
+
+    fn value(&self, count: FishInt) -> Self::Value { count.into() }
+    fn add(&self, a: &Self::Value, b: &Self::Value) -> Self::Value { a + b }
+    fn mul(&self, a: &Self::Value, b: &Self::Value) -> Self::Value { a * b }
+}
+
+struct Modular(u64);
+
+impl Arithmetic for Modular {
+    type Value = u64;
+
+    fn value(&self, count: FishInt) -> Self::Value { count % self.0 }
+    fn add(&self, a: &Self::Value, b: &Self::Value) -> Self::Value { ((*a as u128 + *b as u128) % self.0 as u128) as u64 }
+    fn mul(&self, a: &Self::Value, b: &Self::Value) -> Self::Value { ((*a as u128 * *b as u128) % self.0 as u128) as u64 }
+}
+
+type Matrix<T> = Vec<Vec<T>>;
+
+fn matrix_mul<A: Arithmetic>(arithmetic: &A, a: &Matrix<A::Value>, b: &Matrix<A::Value>) -> Matrix<A::Value> {
+    let size = a.len();
+
+    (0..size).map(|row| {
+        (0..size).map(|column| {
+            (0..size).fold(arithmetic.value(0), |acc, i| arithmetic.add(&acc, &arithmetic.mul(&a[row][i], &b[i][column])))
+        }).collect()
+    }).collect()
+}
+
+// Advances the day schedule by `days` in O(lifecycle^3 * log(days)) by raising the matrix of
+// a single day to the power of `days`
+fn project_with<A: Arithmetic>(arithmetic: &A, population: &[usize], days: u64, lifecycle: usize, reset: usize) -> anyhow::Result<A::Value> {
+    let day_schedule = initial_schedule(population, lifecycle, reset)?;
+    let (zero, one) = (arithmetic.value(0), arithmetic.value(1));
+
+    // The day schedule of the next day is the product of this matrix and the current schedule
+    let mut day_matrix: Matrix<A::Value> = vec![vec![zero.clone(); lifecycle]; lifecycle];
+    for timer in 0..lifecycle-1 {
+        day_matrix[timer][timer+1] = one.clone();
+    }
+    day_matrix[lifecycle-1][0] = one.clone();
+    day_matrix[reset][0] = arithmetic.add(&day_matrix[reset][0], &one);
+
+    let mut power: Matrix<A::Value> = (0..lifecycle)
+        .map(|row| (0..lifecycle).map(|column| if row == column { one.clone() } else { zero.clone() }).collect())
+        .collect();
+
+    let mut remaining = days;
+    while remaining > 0 {
+        if remaining & 1 == 1 {
+            power = matrix_mul(arithmetic, &power, &day_matrix);
+        }
+        day_matrix = matrix_mul(arithmetic, &day_matrix, &day_matrix);
+        remaining >>= 1;
     }
 
+    let total = power.iter()
+        .flat_map(|row| row.iter().zip(&day_schedule))
+        .fold(zero, |acc, (factor, &count)| arithmetic.add(&acc, &arithmetic.mul(factor, &arithmetic.value(count))));
+
+    Ok(total)
+}
+
+/// Same as [`simulate`], but computed in logarithmic time of `days` and with arbitrary precision.
+pub fn project(population: &[usize], days: u64, lifecycle: usize, reset: usize) -> anyhow::Result<num_bigint::BigUint> {
+    project_with(&Exact, population, days, lifecycle, reset)
+}
+
+/// Same as [`project`], but returns the number of lanternfish modulo `modulus`, which keeps
+/// the numbers small for horizons of billions of days.
+pub fn project_modulo(population: &[usize], days: u64, lifecycle: usize, reset: usize, modulus: u64) -> anyhow::Result<u64> {
+    if modulus == 0 {
+        return Err(UsageError("Modulus must be greater than zero".to_string()).into());
+    }
+
+    project_with(&Modular(modulus), population, days, lifecycle, reset)
+}
+
+/// Returns the number of lanternfish after 80 days.
+pub fn run_lanternfish(input: Vec<usize>) -> anyhow::Result<FishInt> {
+    simulate(&input, 80, LANTERNFISH_DAY_LIFECYCLE, LANTERNFISH_RESET_TIMER)
+}
 
-    day_schedule.iter().sum::<FishInt>()
+/// Returns the number of lanternfish after 256 days.
+pub fn run_unlimited_lanternfish(input: Vec<usize>) -> anyhow::Result<FishInt> {
+    simulate(&input, 256, LANTERNFISH_DAY_LIFECYCLE, LANTERNFISH_RESET_TIMER)
 }
 
 #[cfg(test)]
 mod test {
     use crate::day_6::run_unlimited_lanternfish;
-use crate::day_6::run_lanternfish;
+    use crate::day_6::run_lanternfish;
+    use crate::day_6::project;
+    use crate::day_6::project_modulo;
+    use crate::day_6::simulate;
+    use crate::day_6::simulate_series;
 
 const EXAMPLE: &str = "3,4,3,1,2\n";
 
     #[test]
     fn it_passes_lanternfish_example() {
         let answer = run_lanternfish(EXAMPLE.split(',').map(|word| word.trim().parse::<usize>().unwrap()).collect());
-        assert_eq!(answer, 5934)
+        assert_eq!(answer.unwrap(), 5934)
     }
 
     #[test]
     fn it_passes_unlimited_lanternfish_example() {
         let answer = run_unlimited_lanternfish(EXAMPLE.split(',').map(|word| word.trim().parse::<usize>().unwrap()).collect());
-        assert_eq!(answer, 26984457539);
+        assert_eq!(answer.unwrap(), 26984457539);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn it_rejects_timers_outside_lifecycle() {
+        assert!(run_lanternfish(vec![3, 9]).is_err());
+        assert!(run_unlimited_lanternfish(vec![3, 9]).is_err());
+    }
+
+    #[test]
+    fn it_simulates_population_series() {
+        let series = simulate_series(&[3, 4, 3, 1, 2], 18, 9, 6).unwrap();
+
+        assert_eq!(series.len(), 19);
+        assert_eq!(series[0], 5);
+        assert_eq!(series[18], 26);
+    }
+
+    #[test]
+    fn it_rejects_invalid_simulation_parameters() {
+        assert!(simulate(&[3, 4], 10, 5, 5).is_err());
+        assert!(simulate(&[9], 10, 9, 6).is_err());
+        assert!(simulate(&[3, 4, 3, 1, 2], 1000, 9, 6).is_err());
+    }
+
+    #[test]
+    fn it_projects_same_population_as_simulation() {
+        let population = [3, 4, 3, 1, 2];
+
+        for days in [0, 1, 18, 80, 256] {
+            let simulated = simulate(&population, days, 9, 6).unwrap();
+
+            assert_eq!(project(&population, days as u64, 9, 6).unwrap(), simulated.into());
+            assert_eq!(project_modulo(&population, days as u64, 9, 6, 1_000_000_007).unwrap(), simulated % 1_000_000_007);
+        }
+    }
+
+    #[test]
+    fn it_projects_past_overflow_of_simulation() {
+        let population = [3, 4, 3, 1, 2];
+        let exact = project(&population, 1000, 9, 6).unwrap();
+        let modulus = 998_244_353u64;
+
+        assert_eq!(exact.clone() % modulus, project_modulo(&population, 1000, 9, 6, modulus).unwrap().into());
+        assert!(exact > u64::MAX.into());
+    }
+}
@@ -0,0 +1,101 @@
+/// The command line was used incorrectly, e.g. with an unknown subcommand or an invalid range.
+#[derive(Debug)]
+pub struct UsageError(pub String);
+
+impl std::fmt::Display for UsageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for UsageError {}
+
+/// The puzzle input was read, but its contents don't match the expected format.
+#[derive(Debug)]
+pub struct ParseError(pub String);
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A line of the puzzle input has an unexpected token. Lines and columns count from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub line: usize,
+    pub column: usize,
+    // Empty when the line ended too early
+    pub token: String,
+    pub expected: &'static str,
+}
+
+impl std::fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.token.as_str() {
+            "" => write!(f, "Line {}, column {}: expected {}, found the end of the line", self.line, self.column, self.expected),
+            token => write!(f, "Line {}, column {}: expected {}, found '{}'", self.line, self.column, self.expected, token),
+        }
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
+/// Category of a failure, deciding the exit code of the process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Failure,
+    Usage,
+    Input,
+    Parse,
+}
+
+impl ErrorKind {
+    /// Finds the category of `err` by looking for a known error type in its chain of causes.
+    pub fn of(err: &anyhow::Error) -> Self {
+        for cause in err.chain() {
+            if cause.is::<UsageError>() || cause.is::<clap::Error>() {
+                return ErrorKind::Usage;
+            }
+            if cause.is::<std::io::Error>() {
+                return ErrorKind::Input;
+            }
+            if cause.is::<ParseError>() || cause.is::<SyntaxError>() || cause.is::<std::num::ParseIntError>() {
+                return ErrorKind::Parse;
+            }
+        }
+
+        ErrorKind::Failure
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorKind::Failure => 1,
+            ErrorKind::Usage => 2,
+            ErrorKind::Input => 3,
+            ErrorKind::Parse => 4,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::ErrorKind;
+    use crate::error::ParseError;
+    use crate::error::SyntaxError;
+    use anyhow::Context;
+
+    #[test]
+    fn it_classifies_errors_by_their_cause() {
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "missing");
+        let parse = "x".parse::<u8>().unwrap_err();
+
+        assert_eq!(ErrorKind::of(&anyhow::Error::new(io).context("Failed to read")), ErrorKind::Input);
+        assert_eq!(ErrorKind::of(&Err::<(), _>(parse).context("Bad line").unwrap_err()), ErrorKind::Parse);
+        assert_eq!(ErrorKind::of(&ParseError("Unknown command".to_string()).into()), ErrorKind::Parse);
+        assert_eq!(ErrorKind::of(&SyntaxError { line: 3, column: 1, token: "x".to_string(), expected: "a number" }.into()), ErrorKind::Parse);
+        assert_eq!(ErrorKind::of(&anyhow::anyhow!("Something else")), ErrorKind::Failure);
+    }
+}
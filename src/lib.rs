@@ -0,0 +1,30 @@
+//! Solutions to Advent of Code 2021, usable as a library.
+//!
+//! Every `day_N` module exposes the puzzle solvers as plain functions working on line iterators,
+//! together with a `cli` submodule that wires them into the command line through
+//! [`command_line::ChallengeSolutionArgs`].
+
+/// Manifest of expected answers used to verify the solutions.
+pub mod answers;
+/// Repeated timing of the solutions with statistics and baselines.
+pub mod bench;
+/// Registration of the solutions as subcommands and reading of their inputs.
+pub mod command_line;
+/// Utilities shared by all of the solutions.
+pub mod core;
+/// Day 1: Sonar Sweep.
+pub mod day_1;
+/// Day 2: Dive!
+pub mod day_2;
+/// Day 3: Binary Diagnostic.
+pub mod day_3;
+/// Day 4: Giant Squid.
+pub mod day_4;
+/// Day 5: Hydrothermal Venture.
+pub mod day_5;
+/// Day 6: Lanternfish.
+pub mod day_6;
+/// Errors shared by the solutions and the exit codes they map to.
+pub mod error;
+/// Typed answers of the solutions and the formats they are reported in.
+pub mod output;
@@ -1,15 +1,28 @@
-use crate::day_6::cli::UnlimitedLanternfish;
-use crate::day_6::cli::LanternFish;
-use crate::day_5::cli::HydroDiagonal;
-use crate::day_5::cli::HydrotermalVenture;
-use crate::day_4::cli::LosingBoard;
-use crate::day_4::cli::GiantSquid;
-use crate::day_3::cli::LifeSupportRating;
-use crate::day_3::cli::BinaryDiagnostic;
-use crate::day_2::cli::SonarAimedDive;
-use crate::day_2::cli::SonarDive;
-use crate::day_1::cli::SonarSlidingWindow;
-use crate::day_1::cli::SonarSweepDepth;
+use aoc2021::answers::AnswerManifest;
+use aoc2021::answers::Verdict;
+use aoc2021::bench;
+use aoc2021::command_line;
+use aoc2021::error::ErrorKind;
+use aoc2021::error::UsageError;
+use aoc2021::output::OutputFormat;
+use aoc2021::output::SolutionReport;
+use aoc2021::output::write_table;
+use aoc2021::day_6::cli::UnlimitedLanternfish;
+use aoc2021::day_6::cli::LanternFish;
+use aoc2021::day_5::cli::HydroDiagonal;
+use aoc2021::day_5::cli::HydrotermalVenture;
+use aoc2021::day_4::cli::BingoGenerator;
+use aoc2021::day_4::cli::BingoReplay;
+use aoc2021::day_4::cli::BingoRig;
+use aoc2021::day_4::cli::BingoSimulation;
+use aoc2021::day_4::cli::LosingBoard;
+use aoc2021::day_4::cli::GiantSquid;
+use aoc2021::day_3::cli::LifeSupportRating;
+use aoc2021::day_3::cli::BinaryDiagnostic;
+use aoc2021::day_2::cli::SonarAimedDive;
+use aoc2021::day_2::cli::SonarDive;
+use aoc2021::day_1::cli::SonarSlidingWindow;
+use aoc2021::day_1::cli::SonarSweepDepth;
 
 const PROGRAM_NAME: &str = "Advent of Code 2021 Solutions";
 const VERSION: &str = "0.1.0";
@@ -18,25 +31,216 @@ const ABOUT: &str = "The answer programs for Advent of Code 2021.";
 const DESCRIPTION: &str = "\
 This program consists of ready solutions to Advent of Code 2021, a programming challenge
 hosted on 'adventofcode.com'.
-";
 
-mod command_line;
-mod core;
-mod day_1;
-mod day_2;
-mod day_3;
-mod day_4;
-mod day_5;
-mod day_6;
+Exit codes:
+    0  success
+    1  a solution failed or an answer did not match
+    2  invalid usage of the command line
+    3  the input could not be read
+    4  the input could not be parsed
+";
 
-fn time<T>(func: &mut dyn FnMut() -> T) -> T {
+fn time<T>(func: &mut dyn FnMut() -> T) -> (T, std::time::Duration) {
     let start = std::time::Instant::now();
     let result = func();
-    println!("Time elapsed: {}ms", start.elapsed().as_micros() as f32 / 1000.0);
 
-    result
+    (result, start.elapsed())
+}
+
+
+fn input_directory_arg() -> clap::Arg<'static> {
+    clap::Arg::new("input").short('i').long("input").takes_value(true).value_name("DIR")
+        .help("Directory with 'day_N.txt' inputs [default: inputs]")
+}
+
+fn run_single(resolver: &mut command_line::ClapSubcommandResolver, m: &clap::ArgMatches, format: OutputFormat) -> anyhow::Result<()> {
+    let solution_args = resolver.resolve(m)?;
+    let subcommand = solution_args.get_subcommand();
+    let (answer, elapsed) = time(&mut || solution_args.run(m));
+
+    let report = SolutionReport::new(subcommand, answer?, elapsed, command_line::get_input_description(m, subcommand));
+    report.write(format, &mut std::io::stdout(), &mut std::io::stderr())?;
+
+    Ok(())
+}
+
+// Returns the batch solutions of the given days, each with the arguments selecting its input
+// from the directory given to the subcommand
+fn batch_invocations(
+    resolver: &command_line::ClapSubcommandResolver,
+    days: std::ops::RangeInclusive<u32>,
+    submatches: &clap::ArgMatches
+) -> Vec<(&'static str, Vec<String>)> {
+    let args: Vec<String> = match submatches.value_of("input") {
+        Some(directory) => vec!["-i".to_string(), directory.to_string()],
+        None => Vec::new(),
+    };
+
+    resolver.batch_subcommands().into_iter()
+        .filter(|subcommand| command_line::day_of_subcommand(subcommand).parse::<u32>().is_ok_and(|day| days.contains(&day)))
+        .map(|subcommand| (subcommand, args.clone()))
+        .collect()
 }
 
+// Runs every registered solution of the given days against the inputs in the directory
+// given to the subcommand, calling `on_result` with the outcome of each one
+fn run_solutions(
+    resolver: &mut command_line::ClapSubcommandResolver,
+    days: std::ops::RangeInclusive<u32>,
+    submatches: &clap::ArgMatches,
+    on_result: &mut dyn FnMut(&'static str, anyhow::Result<SolutionReport>) -> anyhow::Result<()>
+) -> anyhow::Result<()> {
+    for (subcommand, args) in batch_invocations(resolver, days, submatches) {
+        let result = resolver.matches_for(subcommand, &args).and_then(|m| {
+            let (answer, elapsed) = time(&mut || resolver.get_mut(subcommand)?.run(&m));
+
+            Ok(SolutionReport::new(subcommand, answer?, elapsed, command_line::get_input_description(&m, subcommand)))
+        });
+
+        on_result(subcommand, result)?;
+    }
+
+    Ok(())
+}
+
+fn get_days(submatches: &clap::ArgMatches) -> anyhow::Result<std::ops::RangeInclusive<u32>> {
+    match submatches.value_of("days") {
+        Some(days) => command_line::parse_day_range(days),
+        None => Ok(1..=u32::MAX),
+    }
+}
+
+// Runs every registered solution of the given days, carrying on past the failing ones
+fn run_many(resolver: &mut command_line::ClapSubcommandResolver, days: std::ops::RangeInclusive<u32>, submatches: &clap::ArgMatches, format: OutputFormat) -> anyhow::Result<()> {
+    let mut reports = Vec::new();
+    let mut failures = Vec::new();
+
+    run_solutions(resolver, days, submatches, &mut |subcommand, result| {
+        match result {
+            Ok(report) => {
+                if format != OutputFormat::Text {
+                    report.write(format, &mut std::io::stdout(), &mut std::io::stderr())?;
+                }
+                reports.push(report);
+            },
+            Err(err) => failures.push((subcommand, err)),
+        }
+
+        Ok(())
+    })?;
+
+    if format == OutputFormat::Text {
+        write_table(&reports, &mut std::io::stdout())?;
+    }
+
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    for (subcommand, err) in &failures {
+        eprintln!("{} failed: {:#}", subcommand, err);
+    }
+
+    Err(anyhow::anyhow!("{} of {} solutions failed", failures.len(), failures.len() + reports.len()))
+}
+
+// Compares the answers of the solutions with the ones stored in the answers file
+fn run_verify(resolver: &mut command_line::ClapSubcommandResolver, submatches: &clap::ArgMatches) -> anyhow::Result<()> {
+    let days = get_days(submatches)?;
+    let manifest = AnswerManifest::load(submatches.value_of("answers").expect("Answers file has a default value"))?;
+    let mut mismatches = 0;
+
+    run_solutions(resolver, days, submatches, &mut |subcommand, result| {
+        match result {
+            Ok(report) => match manifest.check(subcommand, &report.answer) {
+                Verdict::Pass => println!("PASS     {}  {}", subcommand, report.answer),
+                Verdict::Missing => println!("MISSING  {}  {}", subcommand, report.answer),
+                Verdict::Fail { expected } => {
+                    mismatches += 1;
+                    println!("FAIL     {}  {} (expected {})", subcommand, report.answer, expected);
+                },
+            },
+            Err(err) => {
+                mismatches += 1;
+                println!("ERROR    {}  {:#}", subcommand, err);
+            },
+        }
+
+        Ok(())
+    })?;
+
+    match mismatches {
+        0 => Ok(()),
+        _ => Err(anyhow::anyhow!("{} solutions did not match the expected answers", mismatches)),
+    }
+}
+
+// Benchmarks either the solutions of a range of days or a single solution invoked with the
+// arguments given after '--'
+fn run_bench(resolver: &mut command_line::ClapSubcommandResolver, submatches: &clap::ArgMatches) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    let parse_count = |name: &str| -> anyhow::Result<usize> {
+        let value = submatches.value_of(name).expect("Argument has a default value");
+        value.parse::<usize>().map_err(|_| UsageError(format!("'{}' is not a valid number for --{}", value, name)).into())
+    };
+
+    let options = bench::BenchmarkOptions { runs: parse_count("runs")?.max(1), warmup: parse_count("warmup")? };
+    let tolerance: f64 = submatches.value_of("tolerance").expect("Tolerance has a default value").parse()
+        .map_err(|_| UsageError("Tolerance must be a number of percents".to_string()))?;
+    let baseline = submatches.value_of("baseline").map(bench::Baseline::load).transpose()?;
+
+    let variants: Vec<&str> = submatches.values_of("variant").map(Iterator::collect).unwrap_or_default();
+
+    // Every invocation is a subcommand with its arguments, and the variant they were extended with
+    let invocations: Vec<(&'static str, Vec<String>, Option<&str>)> = match submatches.values_of("solution") {
+        Some(mut solution) => {
+            let subcommand = solution.next().expect("At least one value is required by clap");
+            let subcommand = *resolver.subcommands().iter().find(|registered| **registered == subcommand)
+                .ok_or_else(|| UsageError(format!("'{}' is not known subcommand.", subcommand)))?;
+            let args: Vec<String> = solution.map(String::from).collect();
+
+            match variants.is_empty() {
+                true => vec![(subcommand, args, None)],
+                false => variants.iter()
+                    .map(|variant| (subcommand, args.iter().cloned().chain(variant.split_whitespace().map(String::from)).collect(), Some(*variant)))
+                    .collect(),
+            }
+        },
+        None if !variants.is_empty() => return Err(UsageError("Variants compare a single solution given after '--'".to_string()).into()),
+        None => batch_invocations(resolver, get_days(submatches)?, submatches).into_iter()
+            .map(|(subcommand, args)| (subcommand, args, None))
+            .collect(),
+    };
+
+    let mut results = Vec::new();
+    for (subcommand, args, variant) in invocations {
+        let m = resolver.matches_for(subcommand, &args)?;
+        let mut result = bench::benchmark(resolver.get_mut(subcommand)?.as_mut(), &m, options)
+            .with_context(|| format!("Benchmark of {} failed", subcommand))?;
+
+        if let Some(variant) = variant {
+            result.label = format!("{} {}", subcommand, variant);
+        }
+        results.push(result);
+    }
+
+    bench::write_table(&results, baseline.as_ref(), &mut std::io::stdout())?;
+
+    if let Some(path) = submatches.value_of("save-baseline") {
+        bench::Baseline::from_results(&results).save(path)?;
+    }
+
+    let regressions: Vec<&str> = results.iter()
+        .filter(|result| baseline.as_ref().and_then(|baseline| baseline.solve_ratio(result)).is_some_and(|ratio| ratio > 1.0 + tolerance / 100.0))
+        .map(|result| result.label.as_str())
+        .collect();
+
+    match regressions.is_empty() {
+        true => Ok(()),
+        false => Err(anyhow::anyhow!("Solutions slower than the baseline by more than {}%: {}", tolerance, regressions.join(", "))),
+    }
+}
 
 fn get_cli_matches(resolver: &mut command_line::ClapSubcommandResolver) -> clap::ArgMatches {
     use command_line::ClapAppExt;
@@ -46,6 +250,41 @@ fn get_cli_matches(resolver: &mut command_line::ClapSubcommandResolver) -> clap:
         .version(VERSION)
         .about(ABOUT)
         .after_help(DESCRIPTION)
+        .arg(clap::Arg::new("format").long("format").takes_value(true).global(true)
+            .possible_values(OutputFormat::VARIANTS).default_value("text")
+            .help("Format in which the answer is printed"))
+        .subcommand(clap::App::new("all")
+            .about("Runs every solution")
+            .arg(input_directory_arg()))
+        .subcommand(clap::App::new("range")
+            .about("Runs the solutions of a range of days, e.g. '1..6'")
+            .arg(clap::Arg::new("days").required(true))
+            .arg(input_directory_arg()))
+        .subcommand(clap::App::new("verify")
+            .about("Checks the answers of the solutions against an answers file")
+            .arg(clap::Arg::new("days").help("Range of days to verify, e.g. '1..6' [default: all]"))
+            .arg(clap::Arg::new("answers").short('a').long("answers").takes_value(true).value_name("FILE")
+                .default_value("answers.toml")
+                .help("TOML file mapping subcommands like '4_2' to their expected answers"))
+            .arg(input_directory_arg()))
+        .subcommand(clap::App::new("bench")
+            .about("Measures parse and solve times of the solutions over repeated runs")
+            .arg(clap::Arg::new("days").help("Range of days to benchmark, e.g. '1..6' [default: all]"))
+            .arg(input_directory_arg())
+            .arg(clap::Arg::new("runs").short('n').long("runs").takes_value(true).default_value("100")
+                .help("Number of measured runs"))
+            .arg(clap::Arg::new("warmup").short('w').long("warmup").takes_value(true).default_value("5")
+                .help("Number of runs done before measuring"))
+            .arg(clap::Arg::new("baseline").long("baseline").takes_value(true).value_name("FILE")
+                .help("Compares the median solve times with the ones saved in FILE"))
+            .arg(clap::Arg::new("save-baseline").long("save-baseline").takes_value(true).value_name("FILE")
+                .help("Saves the median times to FILE"))
+            .arg(clap::Arg::new("tolerance").long("tolerance").takes_value(true).value_name("PERCENT").default_value("10")
+                .help("How much slower than the baseline a solution can be before it is reported"))
+            .arg(clap::Arg::new("variant").long("variant").takes_value(true).multiple_occurrences(true).allow_hyphen_values(true).value_name("ARGS")
+                .help("Runs the solution once more with ARGS added to its arguments, e.g. --variant '--floor dense' --variant '--floor sparse'"))
+            .arg(clap::Arg::new("solution").last(true).multiple_values(true).allow_hyphen_values(true)
+                .help("A single solution with its arguments, e.g. '-- 5_1 -i input.txt'")))
         .aoc_solution(Box::new(SonarSweepDepth {}), resolver)
         .aoc_solution(Box::new(SonarSlidingWindow {}), resolver)
         .aoc_solution(Box::new(SonarDive {}), resolver)
@@ -54,6 +293,10 @@ fn get_cli_matches(resolver: &mut command_line::ClapSubcommandResolver) -> clap:
         .aoc_solution(Box::new(LifeSupportRating {}), resolver)
         .aoc_solution(Box::new(GiantSquid {}), resolver)
         .aoc_solution(Box::new(LosingBoard {}), resolver)
+        .aoc_solution(Box::new(BingoReplay {}), resolver)
+        .aoc_solution(Box::new(BingoSimulation {}), resolver)
+        .aoc_solution(Box::new(BingoRig {}), resolver)
+        .aoc_solution(Box::new(BingoGenerator {}), resolver)
         .aoc_solution(Box::new(HydrotermalVenture {}), resolver)
         .aoc_solution(Box::new(HydroDiagonal {}), resolver)
         .aoc_solution(Box::new(LanternFish {}), resolver)
@@ -63,18 +306,23 @@ fn get_cli_matches(resolver: &mut command_line::ClapSubcommandResolver) -> clap:
 
 
 fn main() {
-    env_logger::init();
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
 
     let mut resolver = command_line::ClapSubcommandResolver::new();
     let m = get_cli_matches(&mut resolver);
 
-    let output = match resolver.resolve(&m) {
-        Ok(solution_args) => { time(&mut || solution_args.run(&m)) },
-        Err(err) => Err(err)
-    };
+    let format: OutputFormat = m.value_of("format").unwrap_or("text").parse().expect("Format is validated by clap");
 
-    match output {
-        Ok(result) => { println!("{}", result) },
-        Err(err) => { log::error!("{}", err) }
+    let output = match m.subcommand() {
+        Some(("all", submatches)) => run_many(&mut resolver, 1..=u32::MAX, submatches, format),
+        Some(("range", submatches)) => get_days(submatches).and_then(|days| run_many(&mut resolver, days, submatches, format)),
+        Some(("verify", submatches)) => run_verify(&mut resolver, submatches),
+        Some(("bench", submatches)) => run_bench(&mut resolver, submatches),
+        _ => run_single(&mut resolver, &m, format),
     };
+
+    if let Err(err) = output {
+        eprintln!("Error: {:?}", err);
+        std::process::exit(ErrorKind::of(&err).exit_code());
+    }
 }
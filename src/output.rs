@@ -0,0 +1,219 @@
+use anyhow::anyhow;
+
+/// The answer computed by a solution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Answer {
+    Integer(i128),
+    // Integers too large for the regular answer, such as populations after millions of days
+    BigInteger(num_bigint::BigUint),
+    Text(String),
+    // Values for plotting, such as a quantity changing over days
+    Series(Vec<i128>),
+}
+
+macro_rules! impl_answer_from_integer {
+    ($($int:ty),*) => {
+        $(
+            impl From<$int> for Answer {
+                fn from(value: $int) -> Self {
+                    Answer::Integer(value as i128)
+                }
+            }
+        )*
+    };
+}
+
+impl_answer_from_integer!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+impl From<num_bigint::BigUint> for Answer {
+    fn from(value: num_bigint::BigUint) -> Self {
+        Answer::BigInteger(value)
+    }
+}
+
+impl From<String> for Answer {
+    fn from(value: String) -> Self {
+        Answer::Text(value)
+    }
+}
+
+impl Answer {
+    /// Returns the answer shortened to a single line, as shown in tables.
+    pub fn summary(&self) -> String {
+        match self {
+            Answer::Integer(value) => value.to_string(),
+            Answer::BigInteger(value) => value.to_string(),
+            Answer::Text(text) => text.lines().next().unwrap_or_default().to_string(),
+            Answer::Series(values) => format!("{} values, last {}", values.len(), values.last().map_or(String::new(), |value| value.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for Answer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Answer::Integer(value) => write!(f, "{}", value),
+            Answer::BigInteger(value) => write!(f, "{}", value),
+            Answer::Text(text) => write!(f, "{}", text),
+            Answer::Series(values) => {
+                let lines: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+                write!(f, "{}", lines.join("\n"))
+            },
+        }
+    }
+}
+
+/// How the reports of solutions are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Tsv,
+}
+
+impl OutputFormat {
+    pub const VARIANTS: [&'static str; 3] = ["text", "json", "tsv"];
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "tsv" => Ok(OutputFormat::Tsv),
+            other => Err(anyhow!("Unknown output format '{}'", other)),
+        }
+    }
+}
+
+/// The outcome of running a single solution.
+pub struct SolutionReport {
+    pub day: String,
+    pub part: String,
+    pub answer: Answer,
+    pub elapsed: std::time::Duration,
+    pub input_path: String,
+}
+
+impl SolutionReport {
+    /// Creates a report for the solution registered under `subcommand`, e.g. '4_2'.
+    pub fn new(subcommand: &str, answer: Answer, elapsed: std::time::Duration, input_path: String) -> Self {
+        let (day, part) = subcommand.split_once('_').unwrap_or((subcommand, ""));
+
+        Self { day: day.to_string(), part: part.to_string(), answer, elapsed, input_path }
+    }
+
+    /// Writes the report in the given format. The text format puts only the answer on `out`
+    /// and the elapsed time on `log`, so the answer can be piped on its own.
+    pub fn write(&self, format: OutputFormat, out: &mut impl std::io::Write, log: &mut impl std::io::Write) -> std::io::Result<()> {
+        match format {
+            OutputFormat::Text => {
+                writeln!(out, "{}", self.answer)?;
+                writeln!(log, "Time elapsed: {}ms", self.elapsed.as_micros() as f32 / 1000.0)
+            },
+            OutputFormat::Tsv => {
+                let answer = match &self.answer {
+                    Answer::Series(values) => values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(","),
+                    answer => escape_tsv(&answer.to_string()),
+                };
+
+                writeln!(out, "{}\t{}\t{}\t{}\t{}",
+                    self.day, self.part, answer, self.elapsed.as_micros(), escape_tsv(&self.input_path))
+            },
+            OutputFormat::Json => {
+                writeln!(out, "{}", self.to_json())
+            },
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let answer = match &self.answer {
+            Answer::Integer(value) => value.to_string(),
+            Answer::BigInteger(value) => value.to_string(),
+            Answer::Text(text) => json_string(text),
+            Answer::Series(values) => {
+                let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+                format!("[{}]", values.join(","))
+            },
+        };
+
+        format!("{{\"day\":{},\"part\":{},\"answer\":{},\"elapsed_us\":{},\"input_path\":{}}}",
+            json_number_or_string(&self.day), json_number_or_string(&self.part), answer,
+            self.elapsed.as_micros(), json_string(&self.input_path))
+    }
+}
+
+/// Writes the reports of several solutions as a table aligned for reading in a terminal.
+pub fn write_table(reports: &[SolutionReport], out: &mut impl std::io::Write) -> std::io::Result<()> {
+    let rows: Vec<[String; 4]> = reports.iter()
+        .map(|report| [
+            report.day.clone(),
+            report.part.clone(),
+            report.answer.summary(),
+            format!("{:.3}ms", report.elapsed.as_micros() as f32 / 1000.0),
+        ])
+        .collect();
+
+    let header = ["Day", "Part", "Answer", "Time"].map(String::from);
+    let mut widths = header.clone().map(|column| column.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(&rows) {
+        writeln!(out, "{:<w0$}  {:<w1$}  {:<w2$}  {:>w3$}", row[0], row[1], row[2], row[3],
+            w0 = widths[0], w1 = widths[1], w2 = widths[2], w3 = widths[3])?;
+    }
+
+    Ok(())
+}
+
+fn json_number_or_string(value: &str) -> String {
+    match value.parse::<u32>() {
+        Ok(number) => number.to_string(),
+        Err(_) => json_string(value),
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped.push('"');
+    escaped
+}
+
+fn escape_tsv(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::output::Answer;
+    use crate::output::SolutionReport;
+
+    #[test]
+    fn it_writes_report_as_json() {
+        let report = SolutionReport::new("4_2", Answer::Text("a \"b\"\n".to_string()),
+            std::time::Duration::from_micros(1500), "inputs/day_4.txt".to_string());
+
+        assert_eq!(report.to_json(),
+            "{\"day\":4,\"part\":2,\"answer\":\"a \\\"b\\\"\\n\",\"elapsed_us\":1500,\"input_path\":\"inputs/day_4.txt\"}");
+    }
+}